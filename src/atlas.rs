@@ -0,0 +1,344 @@
+use std::{collections::HashMap, f32::consts::FRAC_PI_2};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    sprite::Anchor,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+// 图集中的一帧
+#[derive(Debug, Clone)]
+pub struct PlistFrame {
+    pub name: String,
+    // 在TextureAtlas中的下标
+    pub index: usize,
+    // 在图片中占据的区域 旋转过的帧宽高互换
+    pub rect: Rect,
+    // 打包时顺时针旋转了90度
+    pub rotated: bool,
+    // 裁掉透明边后的中心相对原图中心的偏移 y轴向上
+    pub offset: Vec2,
+    // 裁剪后的区域在原图中的位置
+    pub source_color_rect: Rect,
+}
+
+impl PlistFrame {
+    // 还原后的尺寸
+    pub fn size(&self) -> Vec2 {
+        self.source_color_rect.size()
+    }
+
+    // 旋转过的帧需要逆时针转回来
+    pub fn rotation(&self) -> Quat {
+        if self.rotated {
+            Quat::from_rotation_z(FRAC_PI_2)
+        } else {
+            Quat::IDENTITY
+        }
+    }
+
+    // 精灵的锚点 让裁剪后的图片回到原图中的位置 锚点相对图集中的区域 需要跟着旋转
+    pub fn anchor(&self) -> Anchor {
+        let size = self.size();
+        let anchor = -self.offset / size;
+        if self.rotated {
+            Anchor::Custom(Vec2::new(anchor.y, -anchor.x))
+        } else {
+            Anchor::Custom(anchor)
+        }
+    }
+}
+
+// TexturePacker导出的图集 可以按帧名取图集下标
+#[derive(Asset, TypePath, Debug)]
+pub struct PlistAtlas {
+    pub atlas: Handle<TextureAtlas>,
+    pub frames: HashMap<String, PlistFrame>,
+}
+
+impl PlistAtlas {
+    pub fn frame(&self, name: &str) -> Option<&PlistFrame> {
+        self.frames.get(name)
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.frame(name).map(|frame| frame.index)
+    }
+
+    // 世界坐标中的精灵 还原旋转和裁剪偏移
+    #[allow(dead_code)]
+    pub fn sprite(&self, name: &str) -> Option<SpriteSheetBundle> {
+        let frame = self.frame(name)?;
+        Some(SpriteSheetBundle {
+            texture_atlas: self.atlas.clone(),
+            sprite: TextureAtlasSprite {
+                index: frame.index,
+                anchor: frame.anchor(),
+                ..Default::default()
+            },
+            transform: Transform::from_rotation(frame.rotation()),
+            ..Default::default()
+        })
+    }
+
+    // 在UI中显示一帧 style为还原后的大小和位置
+    // 旋转过的帧放在同样大小的节点里 内部节点宽高互换后转回来
+    pub fn spawn_image(&self, parent: &mut ChildBuilder, name: &str, style: Style) -> Entity {
+        let Some(frame) = self.frame(name) else {
+            warn!("图集中没有 {}", name);
+            return parent.spawn(NodeBundle { style, ..default() }).id();
+        };
+        let image = |style: Style, transform: Transform| AtlasImageBundle {
+            style,
+            texture_atlas: self.atlas.clone(),
+            texture_atlas_image: UiTextureAtlasImage {
+                index: frame.index,
+                ..default()
+            },
+            transform,
+            ..default()
+        };
+        if !frame.rotated {
+            return parent.spawn(image(style, Transform::IDENTITY)).id();
+        }
+        let size = frame.size();
+        let (wide, tall) = (size.y / size.x, size.x / size.y);
+        parent
+            .spawn(NodeBundle { style, ..default() })
+            .with_children(|parent| {
+                parent.spawn(image(
+                    Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(100. * wide),
+                        height: Val::Percent(100. * tall),
+                        left: Val::Percent(50. * (1. - wide)),
+                        top: Val::Percent(50. * (1. - tall)),
+                        ..default()
+                    },
+                    Transform::from_rotation(frame.rotation()),
+                ));
+            })
+            .id()
+    }
+}
+
+#[derive(Debug)]
+pub enum PlistError {
+    Io(std::io::Error),
+    Plist(plist::Error),
+    // 坐标字符串格式不对
+    Rect(String),
+    // 图片路径无效
+    Texture(String),
+}
+
+impl std::fmt::Display for PlistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlistError::Io(err) => write!(f, "读取plist失败: {}", err),
+            PlistError::Plist(err) => write!(f, "解析plist失败: {}", err),
+            PlistError::Rect(value) => write!(f, "无法解析坐标: {}", value),
+            PlistError::Texture(path) => write!(f, "无效的图片路径: {}", path),
+        }
+    }
+}
+
+impl std::error::Error for PlistError {}
+
+#[derive(Deserialize)]
+struct PlistFile {
+    frames: HashMap<String, RawFrame>,
+    metadata: PlistMetadata,
+}
+
+// plist中format 2的帧数据
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawFrame {
+    frame: String,
+    #[serde(default)]
+    offset: Option<String>,
+    #[serde(default)]
+    rotated: bool,
+    #[serde(default)]
+    source_color_rect: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlistMetadata {
+    texture_file_name: String,
+    size: String,
+}
+
+// 解析后的图集 帧按名称排序 保证下标稳定
+#[derive(Debug)]
+pub struct PlistSheet {
+    pub texture: String,
+    pub size: Vec2,
+    pub frames: Vec<PlistFrame>,
+}
+
+impl PlistSheet {
+    pub fn parse(bytes: &[u8]) -> Result<Self, PlistError> {
+        let file: PlistFile = plist::from_bytes(bytes).map_err(PlistError::Plist)?;
+        let [width, height] = parse_numbers(&file.metadata.size)?;
+        let mut frames = file.frames.into_iter().collect::<Vec<_>>();
+        frames.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let frames = frames
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, raw))| parse_frame(name, index, raw))
+            .collect::<Result<Vec<_>, PlistError>>()?;
+        Ok(Self {
+            texture: file.metadata.texture_file_name,
+            size: Vec2::new(width, height),
+            frames,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn frame_indices(&self) -> HashMap<String, usize> {
+        self.frames
+            .iter()
+            .map(|frame| (frame.name.clone(), frame.index))
+            .collect()
+    }
+}
+
+fn parse_frame(name: String, index: usize, raw: RawFrame) -> Result<PlistFrame, PlistError> {
+    let [x, y, w, h] = parse_numbers(&raw.frame)?;
+    // 旋转过的帧在图片中宽高互换
+    let rect = if raw.rotated {
+        Rect::new(x, y, x + h, y + w)
+    } else {
+        Rect::new(x, y, x + w, y + h)
+    };
+    let offset = match &raw.offset {
+        Some(offset) => Vec2::from(parse_numbers(offset)?),
+        None => Vec2::ZERO,
+    };
+    let source_color_rect = match &raw.source_color_rect {
+        Some(value) => {
+            let [sx, sy, sw, sh] = parse_numbers(value)?;
+            Rect::new(sx, sy, sx + sw, sy + sh)
+        }
+        None => Rect::new(0., 0., w, h),
+    };
+    Ok(PlistFrame {
+        name,
+        index,
+        rect,
+        rotated: raw.rotated,
+        offset,
+        source_color_rect,
+    })
+}
+
+// 解析 "{{x,y},{w,h}}" 或 "{w,h}" 形式的坐标
+fn parse_numbers<const N: usize>(value: &str) -> Result<[f32; N], PlistError> {
+    let numbers = value
+        .split(['{', '}', ','])
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| PlistError::Rect(value.to_string()))?;
+    numbers
+        .try_into()
+        .map_err(|_| PlistError::Rect(value.to_string()))
+}
+
+#[derive(Default)]
+pub struct PlistAtlasLoader;
+
+impl AssetLoader for PlistAtlasLoader {
+    type Asset = PlistAtlas;
+    type Settings = ();
+    type Error = PlistError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<PlistAtlas, PlistError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(PlistError::Io)?;
+            let sheet = PlistSheet::parse(&bytes)?;
+            // 图片与plist在同一目录
+            let texture_path = load_context
+                .asset_path()
+                .resolve_embed(&sheet.texture)
+                .map_err(|_| PlistError::Texture(sheet.texture.clone()))?;
+            let texture = load_context.load(texture_path);
+            let mut atlas = TextureAtlas::new_empty(texture, sheet.size);
+            for frame in sheet.frames.iter() {
+                atlas.add_texture(frame.rect);
+            }
+            let atlas = load_context.add_labeled_asset("atlas".to_string(), atlas);
+            let frames = sheet
+                .frames
+                .into_iter()
+                .map(|frame| (frame.name.clone(), frame))
+                .collect();
+            Ok(PlistAtlas { atlas, frames })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["plist"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rect_strings() {
+        assert_eq!(
+            parse_numbers::<4>("{{238,646},{116,159}}").unwrap(),
+            [238., 646., 116., 159.]
+        );
+        assert_eq!(parse_numbers::<2>("{-1.5, 2}").unwrap(), [-1.5, 2.]);
+        assert!(parse_numbers::<4>("{1,2}").is_err());
+        assert!(parse_numbers::<2>("{a,2}").is_err());
+    }
+
+    #[test]
+    fn rotated_frame_swaps_texture_rect() {
+        let sheet = PlistSheet::parse(include_bytes!("../assets/image/youqingTip.plist")).unwrap();
+        assert_eq!(sheet.texture, "youqingTip.png");
+        assert_eq!(sheet.size, Vec2::new(402., 918.));
+        let notice = sheet
+            .frames
+            .iter()
+            .find(|frame| frame.name == "xiongmao3.png")
+            .unwrap();
+        assert!(notice.rotated);
+        assert_eq!(notice.rect, Rect::new(2., 690., 382., 916.));
+        assert_eq!(notice.size(), Vec2::new(226., 380.));
+        for frame in sheet.frames.iter() {
+            assert!(frame.rect.max.cmple(sheet.size).all(), "{}", frame.name);
+        }
+    }
+
+    #[test]
+    fn indices_follow_sorted_names() {
+        let sheet = PlistSheet::parse(include_bytes!("../assets/image/public_ui.plist")).unwrap();
+        let names: Vec<&str> = sheet.frames.iter().map(|f| f.name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        let indices = sheet.frame_indices();
+        assert_eq!(indices.len(), 15);
+        assert!(indices.contains_key("head_img_male.png"));
+    }
+}
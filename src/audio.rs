@@ -0,0 +1,434 @@
+use bevy::{audio::Volume, prelude::*, ui::RelativeCursorPosition};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{in_room, AppMode, AppState, MyAssets},
+    game::Bid,
+    pattern::PatternKind,
+    player::VoicePack,
+    room::Room,
+    storage,
+};
+
+// 保存音量的键
+const AUDIO_SETTINGS_KEY: &str = "audio";
+
+pub struct SoundPlugin;
+
+// 音量 0到1 保存在本地
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioSettings {
+    pub music: f32,
+    pub effect: f32,
+    pub voice: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            music: 0.5,
+            effect: 0.8,
+            voice: 1.,
+        }
+    }
+}
+
+// 三种可以单独调节音量的声音
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeKind {
+    /**背景音乐 */
+    Music,
+    /**发牌、出牌音效 */
+    Effect,
+    /**叫地主和出牌语音 */
+    Voice,
+}
+
+impl VolumeKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VolumeKind::Music => "音乐",
+            VolumeKind::Effect => "音效",
+            VolumeKind::Voice => "语音",
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn get(&self, kind: VolumeKind) -> f32 {
+        match kind {
+            VolumeKind::Music => self.music,
+            VolumeKind::Effect => self.effect,
+            VolumeKind::Voice => self.voice,
+        }
+    }
+
+    pub fn set(&mut self, kind: VolumeKind, value: f32) {
+        let value = value.clamp(0., 1.);
+        match kind {
+            VolumeKind::Music => self.music = value,
+            VolumeKind::Effect => self.effect = value,
+            VolumeKind::Voice => self.voice = value,
+        }
+    }
+}
+
+// 背景音乐 登录、大厅和牌桌各一首
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MusicTrack {
+    Login,
+    Lobby,
+    Table,
+}
+
+impl MusicTrack {
+    fn for_state(state: &AppState) -> Option<Self> {
+        match state {
+            AppState::Loading | AppState::Paused => None,
+            AppState::StartMenu => Some(MusicTrack::Login),
+            AppState::Lobby => Some(MusicTrack::Lobby),
+            AppState::InRoom
+            | AppState::DealCard
+            | AppState::Playing
+            | AppState::GameOver
+            | AppState::Replay => Some(MusicTrack::Table),
+        }
+    }
+
+    fn source(&self, assets: &MyAssets) -> Handle<AudioSource> {
+        match self {
+            MusicTrack::Login => assets.login_bg.clone(),
+            MusicTrack::Lobby => assets.lobby_bg.clone(),
+            MusicTrack::Table => assets.game_bg.clone(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct Music(MusicTrack);
+
+// 牌局中的语音
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VoiceLine {
+    Bid(Bid),
+    Play(PatternKind),
+}
+
+impl VoiceLine {
+    // 素材中只有部分语音 缺少的一方用另一种语音包代替
+    fn source(&self, assets: &MyAssets, voice: VoicePack) -> Option<Handle<AudioSource>> {
+        let (male, female) = match self {
+            VoiceLine::Bid(Bid::Pass) => (None, Some(&assets.woman_bu_jiao)),
+            VoiceLine::Bid(_) => (None, Some(&assets.woman_jiao_di_zhu)),
+            VoiceLine::Play(PatternKind::Pair) => (Some(&assets.duizi), Some(&assets.duizi)),
+            VoiceLine::Play(PatternKind::TripleWithPair) => (Some(&assets.man_san_dai_yi), None),
+            VoiceLine::Play(_) => (None, None),
+        };
+        match voice {
+            VoicePack::Male => male.or(female),
+            VoicePack::Female => female.or(male),
+        }
+        .cloned()
+    }
+}
+
+// 已经播放过声音的叫地主和出牌记录数
+#[derive(Resource, Default)]
+struct SoundCursor {
+    dealt: bool,
+    bids: usize,
+    plays: usize,
+}
+
+// 音量按钮 点击展开音量面板
+#[derive(Component)]
+struct VolumeToggle;
+
+#[derive(Component)]
+struct VolumePanel;
+
+// 音量滑条 点击或拖动设置音量
+#[derive(Component)]
+struct VolumeSlider(VolumeKind);
+
+// 滑条中表示当前音量的部分
+#[derive(Component)]
+struct VolumeFill(VolumeKind);
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(storage::load::<AudioSettings>(AUDIO_SETTINGS_KEY).unwrap_or_default())
+            .init_resource::<SoundCursor>()
+            .add_systems(
+                OnExit(AppState::Loading),
+                setup.run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                Update,
+                (
+                    play_music,
+                    apply_music_volume,
+                    toggle_volume_panel,
+                    drag_volume_slider,
+                    refresh_volume_slider,
+                )
+                    .run_if(resource_equals(AppMode::Windowed))
+                    .run_if(resource_exists::<MyAssets>()),
+            )
+            .add_systems(
+                Update,
+                room_sounds
+                    .run_if(in_room)
+                    .run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(OnEnter(AppState::Lobby), reset_cursor);
+    }
+}
+
+fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Percent(2.),
+                    left: Val::Percent(2.),
+                    padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                z_index: ZIndex::Global(20),
+                ..Default::default()
+            },
+            VolumeToggle,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("音量", text_style.clone()));
+        });
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Percent(8.),
+                    left: Val::Percent(2.),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(12.)),
+                    row_gap: Val::Px(10.),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.8).into(),
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(20),
+                ..Default::default()
+            },
+            VolumePanel,
+        ))
+        .with_children(|parent| {
+            for kind in [VolumeKind::Music, VolumeKind::Effect, VolumeKind::Voice] {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(12.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(kind.label(), text_style.clone()));
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(200.),
+                                        height: Val::Px(16.),
+                                        ..Default::default()
+                                    },
+                                    background_color: Color::DARK_GRAY.into(),
+                                    ..Default::default()
+                                },
+                                RelativeCursorPosition::default(),
+                                VolumeSlider(kind),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn((
+                                    NodeBundle {
+                                        style: Style {
+                                            height: Val::Percent(100.),
+                                            ..Default::default()
+                                        },
+                                        background_color: Color::GOLD.into(),
+                                        ..Default::default()
+                                    },
+                                    VolumeFill(kind),
+                                ));
+                            });
+                    });
+            }
+        });
+}
+
+// 切换界面时换背景音乐 房间内的几个状态共用一首
+fn play_music(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    settings: Res<AudioSettings>,
+    state: Res<State<AppState>>,
+    playing: Query<(Entity, &Music)>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+    let track = MusicTrack::for_state(state.get());
+    if playing.iter().any(|(_, music)| Some(music.0) == track) {
+        return;
+    }
+    for (entity, _) in playing.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(track) = track else {
+        return;
+    };
+    commands.spawn((
+        AudioBundle {
+            source: track.source(&assets),
+            settings: PlaybackSettings::LOOP.with_volume(Volume::new_relative(settings.music)),
+        },
+        Music(track),
+    ));
+}
+
+fn apply_music_volume(settings: Res<AudioSettings>, sinks: Query<&AudioSink, With<Music>>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for sink in sinks.iter() {
+        sink.set_volume(settings.music);
+    }
+}
+
+fn play_once(commands: &mut Commands, source: Handle<AudioSource>, volume: f32) {
+    commands.spawn(AudioBundle {
+        source,
+        settings: PlaybackSettings::DESPAWN.with_volume(Volume::new_relative(volume)),
+    });
+}
+
+fn reset_cursor(mut cursor: ResMut<SoundCursor>) {
+    *cursor = SoundCursor::default();
+}
+
+// 房间变化时为新的叫地主和出牌播放声音
+// 重新发牌或开始新的一局时记录变少 从头开始计数
+fn room_sounds(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    settings: Res<AudioSettings>,
+    room: Res<Room>,
+    mut cursor: ResMut<SoundCursor>,
+) {
+    if !room.is_changed() {
+        return;
+    }
+    let Some(game) = &room.game else {
+        *cursor = SoundCursor::default();
+        return;
+    };
+    if !cursor.dealt || game.bids.len() < cursor.bids || game.plays.len() < cursor.plays {
+        play_once(&mut commands, assets.fapai.clone(), settings.effect);
+        *cursor = SoundCursor {
+            dealt: true,
+            bids: 0,
+            plays: 0,
+        };
+    }
+    let voice_pack = |seat: usize| {
+        room.players[seat]
+            .as_ref()
+            .map(|p| p.player.profile.voice)
+            .unwrap_or_default()
+    };
+    let mut voice = |seat: usize, line: VoiceLine| {
+        if let Some(source) = line.source(&assets, voice_pack(seat)) {
+            play_once(&mut commands, source, settings.voice);
+        }
+    };
+    for (seat, bid) in game.bids.iter().skip(cursor.bids) {
+        voice(*seat, VoiceLine::Bid(*bid));
+    }
+    let mut played = false;
+    for record in game.plays.iter().skip(cursor.plays) {
+        if let Some(pattern) = &record.pattern {
+            voice(record.seat, VoiceLine::Play(pattern.kind));
+            played = true;
+        }
+    }
+    if played {
+        play_once(&mut commands, assets.fapai1.clone(), settings.effect);
+    }
+    cursor.bids = game.bids.len();
+    cursor.plays = game.plays.len();
+}
+
+fn toggle_volume_panel(
+    query: Query<&Interaction, (Changed<Interaction>, With<VolumeToggle>)>,
+    mut panel: Query<&mut Visibility, With<VolumePanel>>,
+) {
+    if !query
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        return;
+    }
+    for mut visibility in panel.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+// 按住滑条时跟随鼠标 松开后保存
+fn drag_volume_slider(
+    mouse: Res<Input<MouseButton>>,
+    mut settings: ResMut<AudioSettings>,
+    mut dragged: Local<bool>,
+    sliders: Query<(&Interaction, &RelativeCursorPosition, &VolumeSlider)>,
+) {
+    for (interaction, position, slider) in sliders.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(normalized) = position.normalized else {
+            continue;
+        };
+        let value = normalized.x.clamp(0., 1.);
+        if settings.get(slider.0) != value {
+            settings.set(slider.0, value);
+            *dragged = true;
+        }
+    }
+    if *dragged && mouse.just_released(MouseButton::Left) {
+        storage::save(AUDIO_SETTINGS_KEY, &*settings);
+        *dragged = false;
+    }
+}
+
+fn refresh_volume_slider(
+    settings: Res<AudioSettings>,
+    mut fills: Query<(&mut Style, &VolumeFill)>,
+    added: Query<(), Added<VolumeFill>>,
+) {
+    if !settings.is_changed() && added.is_empty() {
+        return;
+    }
+    for (mut style, fill) in fills.iter_mut() {
+        style.width = Val::Percent(settings.get(fill.0) * 100.);
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
-use rand::{seq::SliceRandom, thread_rng};
 
-use crate::card_deck::{Rank, Suit};
+use crate::{
+    atlas::PlistAtlas,
+    card_deck::{CardDeck, Rank, Suit},
+    common::MyAssets,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize, Component)]
@@ -12,6 +17,13 @@ pub struct Card {
 }
 
 impl Card {
+    // 看不到牌面时发给客户端的牌背 不带真实的花色和点数
+    pub const BACK: Card = Card {
+        suit: Suit::Spade,
+        rank: Rank::Three,
+        hide: true,
+    };
+
     pub fn new(suit: Suit, rank: Rank) -> Self {
         Card {
             suit,
@@ -21,11 +33,15 @@ impl Card {
     }
 
     // 可以添加一些辅助方法，例如翻转牌
+    #[allow(dead_code)]
     pub fn flip(&mut self) {
         self.hide = !self.hide;
     }
 
-    // 可以添加其他与牌相关的功能，如比较大小等
+    // 牌面文字 例如♠A
+    pub fn label(&self) -> String {
+        format!("{}{}", self.suit.symbol(), self.rank.label())
+    }
 }
 
 // 可以定义一些牌的比较功能，例如：
@@ -43,71 +59,181 @@ impl Ord for Card {
     }
 }
 
-// 创建一副牌的函数
-pub fn new_deck() -> Vec<Card> {
-    let suits = [Suit::Club, Suit::Diamond, Suit::Heart, Suit::Spade];
-    let ranks = [
-        Rank::Two,
-        Rank::Three,
-        Rank::Four,
-        Rank::Five,
-        Rank::Six,
-        Rank::Seven,
-        Rank::Eight,
-        Rank::Nine,
-        Rank::Ten,
-        Rank::Jack,
-        Rank::Queen,
-        Rank::King,
-        Rank::Ace,
-    ];
-    let mut deck = Vec::with_capacity(suits.len() * ranks.len() + 2); // 54张牌
-    for &suit in suits.iter() {
-        for &rank in ranks.iter() {
-            deck.push(Card::new(suit, rank));
+// card.plist中每种花色A到K的帧编号 帧名为card_编号.png
+const HEART_FRAMES: [u32; 13] = [9, 8, 7, 6, 55, 54, 53, 52, 51, 50, 5, 49, 48];
+const DIAMOND_FRAMES: [u32; 13] = [47, 46, 45, 44, 43, 42, 41, 40, 4, 39, 38, 37, 36];
+const SPADE_FRAMES: [u32; 13] = [35, 34, 33, 32, 31, 30, 3, 29, 28, 27, 26, 25, 24];
+const CLUB_FRAMES: [u32; 13] = [23, 22, 21, 20, 2, 19, 18, 17, 16, 15, 14, 13, 12];
+const BIG_JOKER_FRAME: u32 = 11;
+const LITTLE_JOKER_FRAME: u32 = 10;
+// 牌背
+const BACK_FRAME: u32 = 1;
+
+// 牌面在card.plist中的帧名
+pub fn frame_name(suit: Suit, rank: Rank) -> String {
+    let frames = match suit {
+        Suit::Heart => HEART_FRAMES,
+        Suit::Diamond => DIAMOND_FRAMES,
+        Suit::Spade => SPADE_FRAMES,
+        Suit::Club => CLUB_FRAMES,
+        Suit::Joker => {
+            let frame = if rank == Rank::BigJoker {
+                BIG_JOKER_FRAME
+            } else {
+                LITTLE_JOKER_FRAME
+            };
+            return format!("card_{}.png", frame);
         }
-    }
-    // 添加大小王
-    deck.push(Card::new(Suit::Joker, Rank::LittleJoker)); // 小王
-    deck.push(Card::new(Suit::Joker, Rank::BigJoker)); // 大王
-    deck
+    };
+    let column = match rank {
+        Rank::Ace => 0,
+        Rank::Two => 1,
+        Rank::Three => 2,
+        Rank::Four => 3,
+        Rank::Five => 4,
+        Rank::Six => 5,
+        Rank::Seven => 6,
+        Rank::Eight => 7,
+        Rank::Nine => 8,
+        Rank::Ten => 9,
+        Rank::Jack => 10,
+        Rank::Queen => 11,
+        Rank::King => 12,
+        Rank::LittleJoker | Rank::BigJoker => unreachable!("王只有Joker花色"),
+    };
+    format!("card_{}.png", frames[column])
 }
 
-pub fn shuffle_deck(mut deck: Vec<Card>) -> Vec<Card> {
-    let mut rng = thread_rng();
-    deck.shuffle(&mut rng);
-    deck
+// 扑克牌图集 由card.plist加载 牌面对应图集下标
+#[derive(Resource, Debug)]
+pub struct CardAtlas {
+    pub atlas: Handle<TextureAtlas>,
+    faces: HashMap<(Suit, Rank), usize>,
+    back: usize,
 }
 
-pub fn get_sprite_index(card: &Card) -> usize {
-    // 计算普通牌在图集中的索引
+impl CardAtlas {
+    // frame_index为plist的帧名到下标 缺少任意一张牌面时返回缺少的帧名
+    pub fn new(
+        atlas: Handle<TextureAtlas>,
+        frame_index: impl Fn(&str) -> Option<usize>,
+    ) -> Result<Self, String> {
+        let index = |name: String| frame_index(&name).ok_or(name);
+        let faces = CardDeck::new()
+            .cards()
+            .iter()
+            .map(|card| {
+                Ok((
+                    (card.suit, card.rank),
+                    index(frame_name(card.suit, card.rank))?,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        let back = index(format!("card_{}.png", BACK_FRAME))?;
+        Ok(Self { atlas, faces, back })
+    }
+}
+
+// 扑克牌在图集中的下标 背面朝上时显示牌背
+pub fn get_sprite_index(card: &Card, atlas: &CardAtlas) -> usize {
     if card.hide {
-        let suit_offset = match card.suit {
-            Suit::Club => 390,
-            Suit::Diamond => 13,
-            Suit::Heart => 0,
-            Suit::Spade => 26,
-            Suit::Joker => 52,
-        };
-        let rank_offset = match card.rank {
-            Rank::Ace => 0,
-            Rank::Two => 1,
-            Rank::Three => 2,
-            Rank::Four => 3,
-            Rank::Five => 4,
-            Rank::Six => 5,
-            Rank::Seven => 6,
-            Rank::Eight => 7,
-            Rank::Nine => 8,
-            Rank::Ten => 9,
-            Rank::Jack => 10,
-            Rank::Queen => 11,
-            Rank::King => 12,
-            Rank::LittleJoker => 1,
-            Rank::BigJoker => 0,
-        };
-        suit_offset + rank_offset
+        atlas.back
     } else {
-        54
+        atlas.faces[&(card.suit, card.rank)]
+    }
+}
+
+// 资源加载完成后从plist图集生成扑克牌下标
+pub fn insert_card_atlas(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    plists: Res<Assets<PlistAtlas>>,
+) {
+    let Some(plist) = plists.get(&assets.card) else {
+        return;
+    };
+    match CardAtlas::new(plist.atlas.clone(), |name| plist.index(name)) {
+        Ok(atlas) => commands.insert_resource(atlas),
+        Err(name) => error!("card.plist缺少帧 {}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atlas::PlistSheet;
+
+    // 图集每帧116x159 间隔2像素 四行花色依次为红心、方块、黑桃、梅花 每行A到K
+    // 第五行依次为大王、小王、牌背
+    fn frame_origin(column: usize, row: usize) -> Vec2 {
+        Vec2::new(2. + 118. * column as f32, 2. + 161. * row as f32)
+    }
+
+    fn load() -> (PlistSheet, CardAtlas) {
+        let sheet = PlistSheet::parse(include_bytes!("../assets/image/card/card.plist")).unwrap();
+        let indices = sheet.frame_indices();
+        let atlas = CardAtlas::new(Handle::default(), |name| indices.get(name).copied()).unwrap();
+        (sheet, atlas)
+    }
+
+    #[test]
+    fn every_face_maps_to_its_frame() {
+        let (sheet, atlas) = load();
+        let ranks = [
+            Rank::Ace,
+            Rank::Two,
+            Rank::Three,
+            Rank::Four,
+            Rank::Five,
+            Rank::Six,
+            Rank::Seven,
+            Rank::Eight,
+            Rank::Nine,
+            Rank::Ten,
+            Rank::Jack,
+            Rank::Queen,
+            Rank::King,
+        ];
+        let suits = [Suit::Heart, Suit::Diamond, Suit::Spade, Suit::Club];
+        let mut expected = vec![];
+        for (row, suit) in suits.into_iter().enumerate() {
+            for (column, rank) in ranks.into_iter().enumerate() {
+                expected.push((Card::new(suit, rank), frame_origin(column, row)));
+            }
+        }
+        expected.push((Card::new(Suit::Joker, Rank::BigJoker), frame_origin(0, 4)));
+        expected.push((
+            Card::new(Suit::Joker, Rank::LittleJoker),
+            frame_origin(1, 4),
+        ));
+        assert_eq!(expected.len(), 54);
+        for (card, origin) in expected {
+            let rect = sheet.frames[get_sprite_index(&card, &atlas)].rect;
+            assert_eq!(rect.min, origin, "{:?}", card);
+            assert_eq!(rect.size(), Vec2::new(116., 159.), "{:?}", card);
+        }
+    }
+
+    #[test]
+    fn hidden_card_shows_back() {
+        let (sheet, atlas) = load();
+        let mut card = Card::new(Suit::Spade, Rank::Ace);
+        card.flip();
+        assert_eq!(get_sprite_index(&card, &atlas), atlas.back);
+        assert_eq!(sheet.frames[atlas.back].rect.min, frame_origin(2, 4));
+    }
+
+    #[test]
+    fn faces_are_distinct() {
+        let (_, atlas) = load();
+        let mut indices: Vec<usize> = CardDeck::new()
+            .cards()
+            .iter()
+            .map(|card| get_sprite_index(card, &atlas))
+            .collect();
+        indices.push(atlas.back);
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), 55);
     }
 }
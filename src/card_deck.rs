@@ -1,3 +1,4 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 use crate::card::Card;
@@ -35,37 +36,278 @@ pub enum Rank {
     BigJoker,
 }
 
+// 除大小王以外的13种点数
+pub const RANKS: [Rank; 13] = [
+    Rank::Two,
+    Rank::Three,
+    Rank::Four,
+    Rank::Five,
+    Rank::Six,
+    Rank::Seven,
+    Rank::Eight,
+    Rank::Nine,
+    Rank::Ten,
+    Rank::Jack,
+    Rank::Queen,
+    Rank::King,
+    Rank::Ace,
+];
+
+impl Suit {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Suit::Club => "♣",
+            Suit::Diamond => "♦",
+            Suit::Heart => "♥",
+            Suit::Spade => "♠",
+            Suit::Joker => "",
+        }
+    }
+}
+
+impl Rank {
+    // 斗地主中的大小 3最小 A之后是2 王最大
+    pub fn value(&self) -> u8 {
+        match self {
+            Rank::Three => 3,
+            Rank::Four => 4,
+            Rank::Five => 5,
+            Rank::Six => 6,
+            Rank::Seven => 7,
+            Rank::Eight => 8,
+            Rank::Nine => 9,
+            Rank::Ten => 10,
+            Rank::Jack => 11,
+            Rank::Queen => 12,
+            Rank::King => 13,
+            Rank::Ace => 14,
+            Rank::Two => 15,
+            Rank::LittleJoker => 16,
+            Rank::BigJoker => 17,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        RANKS
+            .into_iter()
+            .chain([Rank::LittleJoker, Rank::BigJoker])
+            .find(|rank| rank.value() == value)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rank::Two => "2",
+            Rank::Three => "3",
+            Rank::Four => "4",
+            Rank::Five => "5",
+            Rank::Six => "6",
+            Rank::Seven => "7",
+            Rank::Eight => "8",
+            Rank::Nine => "9",
+            Rank::Ten => "10",
+            Rank::Jack => "J",
+            Rank::Queen => "Q",
+            Rank::King => "K",
+            Rank::Ace => "A",
+            Rank::LittleJoker => "小王",
+            Rank::BigJoker => "大王",
+        }
+    }
+}
+
+// 一副或多副牌 牌堆顶在前
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CardDeck {
     cards: Vec<Card>,
 }
 
+impl Default for CardDeck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CardDeck {
+    // 一副54张的牌 按花色和点数排列
     pub fn new() -> Self {
+        Self::with_decks(1)
+    }
+
+    // 多副牌混在一起 例如两副108张
+    pub fn with_decks(decks: usize) -> Self {
         let suits = [Suit::Club, Suit::Diamond, Suit::Heart, Suit::Spade];
-        let ranks = [
-            Rank::Two,
-            Rank::Three,
-            Rank::Four,
-            Rank::Five,
-            Rank::Six,
-            Rank::Seven,
-            Rank::Eight,
-            Rank::Nine,
-            Rank::Ten,
-            Rank::Jack,
-            Rank::Queen,
-            Rank::King,
-            Rank::Ace,
-        ];
-        let mut cards = Vec::with_capacity(suits.len() * ranks.len() + 2); // 54张牌
-        for &suit in suits.iter() {
-            for &rank in ranks.iter() {
-                cards.push(Card::new(suit, rank));
+        let mut cards = Vec::with_capacity((suits.len() * RANKS.len() + 2) * decks);
+        for _ in 0..decks {
+            for &suit in suits.iter() {
+                for &rank in RANKS.iter() {
+                    cards.push(Card::new(suit, rank));
+                }
             }
+            // 添加大小王
+            cards.push(Card::new(Suit::Joker, Rank::LittleJoker));
+            cards.push(Card::new(Suit::Joker, Rank::BigJoker));
         }
-        // 添加大小王
-        cards.push(Card::new(Suit::Joker, Rank::LittleJoker)); // 小王
-        cards.push(Card::new(Suit::Joker, Rank::BigJoker)); // 大王
         Self { cards }
     }
+
+    // 按给定顺序组成牌堆 第一张在堆顶
+    pub fn from_cards(cards: Vec<Card>) -> Self {
+        Self { cards }
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    // 相同的种子洗出相同的顺序 用于复盘和测试
+    pub fn shuffle_with_seed(&mut self, seed: u64) {
+        self.cards.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+
+    // 切牌 把前at张移到牌堆底
+    pub fn cut(&mut self, at: usize) {
+        let len = self.cards.len();
+        if len > 0 {
+            self.cards.rotate_left(at % len);
+        }
+    }
+
+    // 从牌堆顶摸n张 不够时摸完为止
+    pub fn draw(&mut self, n: usize) -> Vec<Card> {
+        let n = n.min(self.cards.len());
+        self.cards.drain(..n).collect()
+    }
+
+    // 留下remainder张作为底牌 其余轮流发给hands个人 每次连续发chunk张给同一个人
+    // 最后一圈不足时按各人应得的张数补齐 返回每人的手牌和底牌 发完后牌堆为空
+    pub fn deal_in_chunks(
+        &mut self,
+        hands: usize,
+        remainder: usize,
+        chunk: usize,
+    ) -> (Vec<Vec<Card>>, Vec<Card>) {
+        let mut dealt: Vec<Vec<Card>> = vec![vec![]; hands];
+        let count = self.cards.len().saturating_sub(remainder);
+        if hands > 0 {
+            // 不能整除时前面的人多一张
+            let sizes: Vec<usize> = (0..hands)
+                .map(|seat| count / hands + usize::from(seat < count % hands))
+                .collect();
+            let chunk = chunk.max(1);
+            let mut seat = 0;
+            while dealt
+                .iter()
+                .zip(&sizes)
+                .any(|(hand, size)| hand.len() < *size)
+            {
+                let n = chunk.min(sizes[seat] - dealt[seat].len());
+                let cards = self.draw(n);
+                dealt[seat].extend(cards);
+                seat = (seat + 1) % hands;
+            }
+        }
+        let bottom = self.draw(self.cards.len());
+        (dealt, bottom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn builds_single_and_multi_deck() {
+        let deck = CardDeck::new();
+        assert_eq!(deck.cards().len(), 54);
+        let unique: HashSet<&Card> = deck.cards().iter().collect();
+        assert_eq!(unique.len(), 54);
+        let shoe = CardDeck::with_decks(2);
+        assert_eq!(shoe.cards().len(), 108);
+        let big_jokers = shoe
+            .cards()
+            .iter()
+            .filter(|card| card.rank == Rank::BigJoker)
+            .count();
+        assert_eq!(big_jokers, 2);
+    }
+
+    #[test]
+    fn seeded_shuffle_is_repeatable() {
+        let mut a = CardDeck::new();
+        let mut b = CardDeck::new();
+        a.shuffle_with_seed(42);
+        b.shuffle_with_seed(42);
+        assert_eq!(a, b);
+        assert_ne!(a, CardDeck::new());
+        b.shuffle_with_seed(7);
+        assert_ne!(a, b);
+        let mut sorted = a.cards().to_vec();
+        sorted.sort();
+        let mut fresh = CardDeck::new().cards().to_vec();
+        fresh.sort();
+        assert_eq!(sorted, fresh);
+    }
+
+    #[test]
+    fn cut_and_draw() {
+        let mut deck = CardDeck::new();
+        let third = deck.cards()[2];
+        deck.cut(56);
+        assert_eq!(deck.cards()[0], third);
+        assert_eq!(deck.cards().len(), 54);
+        let drawn = deck.draw(5);
+        assert_eq!(drawn.len(), 5);
+        assert_eq!(drawn[0], third);
+        assert_eq!(deck.cards().len(), 49);
+        assert_eq!(deck.draw(100).len(), 49);
+        assert_eq!(deck.cards().len(), 0);
+        deck.cut(3);
+        assert!(deck.draw(1).is_empty());
+    }
+
+    #[test]
+    fn deals_with_remainder() {
+        let mut deck = CardDeck::new();
+        let first = deck.cards()[0];
+        let (hands, bottom) = deck.deal_in_chunks(3, 3, 1);
+        assert_eq!(hands.iter().map(Vec::len).collect::<Vec<_>>(), [17, 17, 17]);
+        assert_eq!(hands[0][0], first);
+        assert_eq!(bottom.len(), 3);
+        assert_eq!(deck.cards().len(), 0);
+
+        let mut shoe = CardDeck::with_decks(2);
+        let (hands, bottom) = shoe.deal_in_chunks(4, 8, 1);
+        assert_eq!(
+            hands.iter().map(Vec::len).collect::<Vec<_>>(),
+            [25, 25, 25, 25]
+        );
+        assert_eq!(bottom.len(), 8);
+
+        // 不能整除时前面的人多一张
+        let mut deck = CardDeck::new();
+        let (hands, bottom) = deck.deal_in_chunks(4, 0, 1);
+        assert_eq!(
+            hands.iter().map(Vec::len).collect::<Vec<_>>(),
+            [14, 14, 13, 13]
+        );
+        assert!(bottom.is_empty());
+    }
+
+    #[test]
+    fn deals_in_chunks() {
+        let mut deck = CardDeck::new();
+        let top: Vec<Card> = deck.cards()[..6].to_vec();
+        let (hands, bottom) = deck.deal_in_chunks(3, 3, 3);
+        assert_eq!(hands.iter().map(Vec::len).collect::<Vec<_>>(), [17, 17, 17]);
+        assert_eq!(hands[0][..3], top[..3]);
+        assert_eq!(hands[1][..3], top[3..]);
+        assert_eq!(bottom.len(), 3);
+
+        // 每次一张时与deal相同
+        let (a, _) = CardDeck::new().deal_in_chunks(4, 0, 1);
+        let (b, _) = CardDeck::new().deal_in_chunks(4, 0, 1);
+        assert_eq!(a, b);
+    }
 }
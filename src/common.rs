@@ -1,12 +1,15 @@
-use std::sync::{Arc, RwLock};
-
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
-use bevy_ggrs::*;
-use bevy_matchbox::{matchbox_socket::WebRtcSocket, prelude::*};
+use bevy_matchbox::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::{player::Player, room::Room};
+use crate::{
+    atlas::PlistAtlas,
+    game::GameAction,
+    player::Player,
+    room::{JoinRoomError, Room},
+    stats::{PlayerStats, SettlementRecord},
+};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, States, Default, Reflect)]
 pub enum AppState {
@@ -19,6 +22,35 @@ pub enum AppState {
     Playing,
     Paused,
     GameOver,
+    // 回放记录的牌局
+    Replay,
+}
+
+// 是否在房间内 包括等待、发牌、游戏中和结算
+pub fn in_room(state: Res<State<AppState>>) -> bool {
+    matches!(
+        state.get(),
+        AppState::InRoom | AppState::DealCard | AppState::Playing | AppState::GameOver
+    )
+}
+
+// 是否显示牌桌 房间内或者回放中
+pub fn on_table(state: Res<State<AppState>>) -> bool {
+    matches!(
+        state.get(),
+        AppState::InRoom
+            | AppState::DealCard
+            | AppState::Playing
+            | AppState::GameOver
+            | AppState::Replay
+    )
+}
+
+// 运行模式 无窗口模式下不创建窗口、渲染器和音频 界面相关的系统也不会运行
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Resource)]
+pub enum AppMode {
+    Windowed,
+    Headless,
 }
 
 #[derive(AssetCollection, Resource)]
@@ -29,36 +61,30 @@ pub struct MyAssets {
     pub duizi: Handle<AudioSource>,
     #[asset(path = "embedded://sounds/fapai.mp3")]
     pub fapai: Handle<AudioSource>,
+    // 出牌的音效
+    #[asset(path = "embedded://sounds/fapai1.mp3")]
+    pub fapai1: Handle<AudioSource>,
     #[asset(path = "embedded://sounds/login_bg.ogg")]
     pub login_bg: Handle<AudioSource>,
+    // 大厅背景音乐
+    #[asset(path = "embedded://sounds/start.mp3")]
+    pub lobby_bg: Handle<AudioSource>,
     #[asset(path = "embedded://sounds/man_san_dai_yi_dui.ogg")]
     pub man_san_dai_yi: Handle<AudioSource>,
-    #[asset(path = "embedded://sounds/start_a.ogg")]
-    pub start: Handle<AudioSource>,
     #[asset(path = "embedded://sounds/woman_bu_jiao.ogg")]
     pub woman_bu_jiao: Handle<AudioSource>,
     #[asset(path = "embedded://sounds/woman_jiao_di_zhu.ogg")]
     pub woman_jiao_di_zhu: Handle<AudioSource>,
 
-    #[asset(texture_atlas(
-        tile_size_x = 116.,
-        tile_size_y = 159.,
-        columns = 13,
-        rows = 5,
-        padding_x = 2.,
-        padding_y = 2.,
-        offset_x = 0.,
-        offset_y = 0.
-    ))]
-    #[asset(path = "embedded://image/card/card.png")]
-    pub card: Handle<TextureAtlas>,
-
-    #[asset(path = "embedded://image/youqing_girl.png")]
-    pub you_qing_girl: Handle<Image>,
-    #[asset(path = "embedded://image/youqing_boy.png")]
-    pub you_qing_boy: Handle<Image>,
-    #[asset(path = "embedded://image/tip.png")]
-    pub tip: Handle<Image>,
+    #[asset(path = "embedded://image/card/card.plist")]
+    pub card: Handle<PlistAtlas>,
+
+    // 公共界面图集 头像、按钮和边框
+    #[asset(path = "embedded://image/public_ui.plist")]
+    pub public_ui: Handle<PlistAtlas>,
+    // 大厅的人物和健康游戏公告
+    #[asset(path = "embedded://image/youqingTip.plist")]
+    pub youqing_tip: Handle<PlistAtlas>,
     #[asset(path = "embedded://bg_login.jpg")]
     pub bg_login: Handle<Image>,
     #[asset(path = "embedded://loading_bg.png")]
@@ -75,10 +101,68 @@ pub struct MyAssets {
     pub check_mark: Handle<Image>,
     #[asset(path = "embedded://image/btn_create_room.png")]
     pub btn_create_room: Handle<Image>,
+    #[asset(path = "embedded://image/img_loginlist_login_close.png")]
+    pub btn_close: Handle<Image>,
+    #[asset(path = "embedded://image/joininputBg.png")]
+    pub join_input_bg: Handle<Image>,
+    #[asset(
+        paths(
+            "embedded://image/join_0.png",
+            "embedded://image/join_1.png",
+            "embedded://image/join_2.png",
+            "embedded://image/join_3.png",
+            "embedded://image/join_4.png",
+            "embedded://image/join_5.png",
+            "embedded://image/join_6.png",
+            "embedded://image/join_7.png",
+            "embedded://image/join_8.png",
+            "embedded://image/join_9.png"
+        ),
+        collection(typed)
+    )]
+    pub join_digits: Vec<Handle<Image>>,
+    #[asset(path = "embedded://image/join_zuoxia.png")]
+    pub join_clear: Handle<Image>,
+    #[asset(path = "embedded://image/join_youxia.png")]
+    pub join_back: Handle<Image>,
     #[asset(path = "embedded://table_bg_1.jpg")]
     pub table_bg_1: Handle<Image>,
     #[asset(path = "embedded://image/room_touxiang.png")]
     pub room_touxiang: Handle<Image>,
+    #[asset(path = "embedded://image/btn_return_room.png")]
+    pub btn_return_room: Handle<Image>,
+    #[asset(path = "embedded://image/headimage/img_Card_dizhu.png")]
+    pub landlord_badge: Handle<Image>,
+    #[asset(path = "embedded://image/btnready.png")]
+    pub btn_ready: Handle<Image>,
+    #[asset(path = "embedded://image/pm_ready.png")]
+    pub pm_ready: Handle<Image>,
+    #[asset(path = "embedded://image/gray_ready.png")]
+    pub gray_ready: Handle<Image>,
+    #[asset(path = "embedded://image/bt_start_0.png")]
+    pub btn_start: Handle<Image>,
+    #[asset(path = "embedded://image/button/qiangzhuang.png")]
+    pub btn_rob: Handle<Image>,
+    #[asset(path = "embedded://image/button/buqiangzhuang.png")]
+    pub btn_no_rob: Handle<Image>,
+    #[asset(path = "embedded://image/button/btn_chupai.png")]
+    pub btn_play: Handle<Image>,
+    #[asset(path = "embedded://image/button/btn_bujiao.png")]
+    pub btn_pass: Handle<Image>,
+    #[asset(
+        paths(
+            "embedded://image/btn_happy_2.png",
+            "embedded://image/btn_happy_3.png",
+            "embedded://image/btn_happy_4.png",
+            "embedded://image/btn_happy_5.png"
+        ),
+        collection(typed)
+    )]
+    pub btn_stakes: Vec<Handle<Image>>,
+    #[asset(path = "embedded://image/room_money_frame.png")]
+    pub room_money_frame: Handle<Image>,
+    #[asset(path = "embedded://image/yuanbaoIcon.png")]
+    pub yuanbao_icon: Handle<Image>,
     #[asset(path = "embedded://font/FZKTJW.ttf")]
     pub font: Handle<Font>,
 }
@@ -89,77 +173,6 @@ pub enum MenuButton {
     Weixin,
 }
 
-#[derive(Debug, Resource)]
-pub struct GameSounds {
-    pub game_bg: Handle<AudioSource>,
-    pub duizi: Handle<AudioSource>,
-    pub fapai: Handle<AudioSource>,
-    pub login_bg: Handle<AudioSource>,
-    pub man_san_dai_yi: Handle<AudioSource>,
-    pub start: Handle<AudioSource>,
-    pub woman_bu_jiao: Handle<AudioSource>,
-    pub woman_jiao_di_zhu: Handle<AudioSource>,
-}
-
-#[derive(Debug, Resource)]
-pub struct GameTextureAtlasHandles {
-    pub card: Handle<TextureAtlas>,
-    pub you_qing: Handle<TextureAtlas>,
-}
-
-#[derive(Debug, Resource, Clone)]
-pub struct PokerSocket(pub Arc<RwLock<WebRtcSocket>>);
-impl ggrs::NonBlockingSocket<PeerId> for PokerSocket {
-    fn send_to(&mut self, msg: &ggrs::Message, addr: &PeerId) {
-        self.0
-            .write()
-            // if the lock is poisoned, we're already doomed, time to panic
-            .expect("failed to lock socket for reading")
-            .send_to(msg, addr);
-    }
-
-    fn receive_all_messages(&mut self) -> Vec<(PeerId, ggrs::Message)> {
-        self.0
-            .write()
-            // if the lock is poisoned, we're already doomed, time to panic
-            .expect("failed to lock socket for receiving")
-            .receive_all_messages()
-    }
-}
-
-// #[derive(Serialize, Deserialize, Debug, Clone)]
-// pub enum PokerMessage {
-//     Chat { handle: GgrsHandle, message: String },
-// }
-pub fn menu_button_press_system(
-    mut commands: Commands,
-    query: Query<(&Interaction, &MenuButton), (Changed<Interaction>, With<Button>)>,
-    mut state: ResMut<NextState<AppState>>,
-) {
-    for (interaction, button) in query.iter() {
-        if *interaction == Interaction::Pressed {
-            match button {
-                MenuButton::Traveler => {
-                    let room_url = "ws://127.0.0.1:3536/extreme_bevy?next=2";
-                    // let (socket, message_loop) = WebRtcSocket::builder(room_url)
-                    //     .add_unreliable_channel()
-                    //     .add_reliable_channel()
-                    //     .build();
-                    info!("connecting to matchbox server: {room_url}");
-                    commands.insert_resource(MatchboxSocket::new_ggrs(room_url));
-                    state.set(AppState::Lobby);
-                }
-                MenuButton::Weixin => {
-                    // println!("weixin");
-                }
-            }
-        }
-    }
-}
-
-#[derive(Debug, Resource)]
-pub struct Cards {}
-
 pub fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
     for entity in &to_despawn {
         commands.entity(entity).despawn_recursive();
@@ -169,9 +182,27 @@ pub fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut comm
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Event {
     SyncRoom(Room),
-    JoinRoom,
+    // 按房间号请求加入 由房主处理
+    JoinRoom { code: u32, password: Option<String> },
     JoinRoomSuccess(Room),
-    Test(i32),
+    JoinRoomRejected(JoinRoomError),
+    // 离开房间 由房主处理
+    LeaveRoom(u32),
+    // 被房主踢出房间
+    Kicked(u32),
+    // 最后一名玩家离开 房间解散
+    DissolveRoom(u32),
+    // 准备或取消准备 show_hand表示明牌开始 由房主处理
+    Ready { ready: bool, show_hand: bool },
+    // 牌局中的操作 由房主校验执行
+    GameAction(GameAction),
+    // 房主把结束牌局的结算发给排行榜服务器记录
+    Settlement(Vec<SettlementRecord>),
+    // 排行榜服务器在客户端连入时发出 客户端据此找到服务器的连接
+    StatsServerOnline,
+    // 打开排行榜时向排行榜服务器请求
+    LeaderboardRequest,
+    Leaderboard(Vec<(u64, PlayerStats)>),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -180,43 +211,99 @@ pub struct AddressedEvent {
     pub(crate) event: Event,
 }
 
+// 不可靠通道 用于频繁的房间同步和普通操作
+const UNRELIABLE_CHANNEL: usize = 0;
+// 可靠通道 用于必须送达的消息 例如明牌
+const RELIABLE_CHANNEL: usize = 1;
+
 #[derive(Resource)]
 pub struct Socket {
-    unreliable: MatchboxSocket<SingleChannel>,
+    // 测试中没有连接
+    socket: Option<MatchboxSocket<MultipleChannels>>,
+    // 每帧开始时更新一次的连接列表和这一帧的连接变化
+    // update_peers会取走连接变化 多处调用时后面的系统会漏掉断开事件
+    peers: Vec<PeerId>,
+    peer_changes: Vec<(PeerId, PeerState)>,
 }
 
 impl Socket {
-    pub fn new(unreliable: MatchboxSocket<SingleChannel>) -> Self {
-        Self { unreliable }
+    pub fn new(socket: MatchboxSocket<MultipleChannels>) -> Self {
+        Self {
+            socket: Some(socket),
+            peers: vec![],
+            peer_changes: vec![],
+        }
     }
 
-    pub fn receive_unreliable(&mut self) -> Vec<AddressedEvent> {
-        self.unreliable
-            .receive()
-            .iter()
-            .map(|(_, payload)| payload)
-            .filter_map(|payload| ciborium::de::from_reader(&payload[..]).ok())
+    // 不连接信令服务器的Socket 测试中收不到也发不出消息
+    #[cfg(test)]
+    pub fn offline() -> Self {
+        Self {
+            socket: None,
+            peers: vec![],
+            peer_changes: vec![],
+        }
+    }
+
+    // 收取两个通道的消息
+    pub fn receive(&mut self) -> Vec<AddressedEvent> {
+        let Some(socket) = self.socket.as_mut() else {
+            return vec![];
+        };
+        [UNRELIABLE_CHANNEL, RELIABLE_CHANNEL]
+            .into_iter()
+            .flat_map(|channel| socket.channel_mut(channel).receive())
+            .filter_map(|(peer, payload)| {
+                let mut event: AddressedEvent = ciborium::de::from_reader(&payload[..]).ok()?;
+                // 发送者以实际的连接为准 不能冒充其他客户端
+                event.src.id = peer;
+                Some(event)
+            })
             .collect()
     }
 
-    pub fn send_unreliable(&mut self, event: AddressedEvent, peers: Vec<PeerId>) {
+    fn send(&mut self, channel: usize, event: AddressedEvent, peers: Vec<PeerId>) {
+        let Some(socket) = self.socket.as_mut() else {
+            return;
+        };
         let mut payload = Vec::new();
         ciborium::ser::into_writer(&event, &mut payload).unwrap();
         for peer in peers {
-            self.unreliable.send(payload.clone().into(), peer);
+            socket
+                .channel_mut(channel)
+                .send(payload.clone().into(), peer);
         }
     }
 
+    pub fn send_unreliable(&mut self, event: AddressedEvent, peers: Vec<PeerId>) {
+        self.send(UNRELIABLE_CHANNEL, event, peers);
+    }
+
+    pub fn send_reliable(&mut self, event: AddressedEvent, peers: Vec<PeerId>) {
+        self.send(RELIABLE_CHANNEL, event, peers);
+    }
+
     pub fn unreliable_id(&mut self) -> Option<PeerId> {
-        self.unreliable.id()
+        self.socket.as_mut()?.id()
     }
 
-    pub fn unreliable_connected_peers(&mut self) -> impl std::iter::Iterator<Item = PeerId> + '_ {
-        self.update_peers_unreliable();
-        self.unreliable.connected_peers()
+    // 当前连接的客户端
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.peers.clone()
     }
 
-    pub fn update_peers_unreliable(&mut self) -> Vec<(PeerId, PeerState)> {
-        self.unreliable.update_peers()
+    // 这一帧连入或断开的客户端
+    pub fn peer_changes(&self) -> Vec<(PeerId, PeerState)> {
+        self.peer_changes.clone()
     }
 }
+
+// 每帧只更新一次连接状态 其他系统读取缓存的结果
+pub fn update_peers(mut socket: ResMut<Socket>) {
+    let socket = socket.as_mut();
+    let Some(inner) = socket.socket.as_mut() else {
+        return;
+    };
+    socket.peer_changes = inner.update_peers();
+    socket.peers = inner.connected_peers().collect();
+}
@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+
+use crate::{
+    card::Card,
+    card_deck::{CardDeck, Rank},
+    common::{in_room, AppMode, AppState, MyAssets},
+    game::Game,
+    player::Player,
+    room::{Room, RoomUIComponent},
+};
+
+// 记牌器中的点数 从大到小
+const COUNTER_RANKS: [Rank; 15] = [
+    Rank::BigJoker,
+    Rank::LittleJoker,
+    Rank::Two,
+    Rank::Ace,
+    Rank::King,
+    Rank::Queen,
+    Rank::Jack,
+    Rank::Ten,
+    Rank::Nine,
+    Rank::Eight,
+    Rank::Seven,
+    Rank::Six,
+    Rank::Five,
+    Rank::Four,
+    Rank::Three,
+];
+
+pub struct CardCounterPlugin;
+
+// 记牌器面板 房间规则开启且在牌局中时显示
+#[derive(Component)]
+pub struct CardCounterPanel;
+
+// 某个点数剩余张数的文字
+#[derive(Component)]
+pub struct CardCounterCell(pub Rank);
+
+impl Plugin for CardCounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                from: AppState::Lobby,
+                to: AppState::InRoom,
+            },
+            setup.run_if(resource_equals(AppMode::Windowed)),
+        )
+        .add_systems(
+            Update,
+            refresh_counter
+                .run_if(in_room)
+                .run_if(resource_equals(AppMode::Windowed)),
+        );
+    }
+}
+
+// 每个点数还有多少张没出现过 已经打出的牌和自己的手牌都不算
+pub fn remaining_counts(game: &Game, hand: &[Card]) -> [usize; 15] {
+    let deck = CardDeck::with_decks(game.rules.variant.decks());
+    let seen: Vec<&Card> = game
+        .plays
+        .iter()
+        .flat_map(|record| record.cards.iter())
+        .chain(hand.iter())
+        .collect();
+    COUNTER_RANKS.map(|rank| {
+        let total = deck.cards().iter().filter(|card| card.rank == rank).count();
+        let seen = seen.iter().filter(|card| card.rank == rank).count();
+        total.saturating_sub(seen)
+    })
+}
+
+fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+    let label_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 18.,
+        color: Color::GOLD,
+    };
+    let count_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(10.),
+                    width: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                visibility: Visibility::Hidden,
+                ..Default::default()
+            },
+            CardCounterPanel,
+            RoomUIComponent,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::axes(Val::Px(8.), Val::Px(4.)),
+                        column_gap: Val::Px(2.),
+                        ..Default::default()
+                    },
+                    background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for rank in COUNTER_RANKS {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    min_width: Val::Px(36.),
+                                    flex_direction: FlexDirection::Column,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    rank.label(),
+                                    label_style.clone(),
+                                ));
+                                parent.spawn((
+                                    TextBundle::from_section("", count_style.clone()),
+                                    CardCounterCell(rank),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+// 每次出牌后刷新 出完的点数置灰
+fn refresh_counter(
+    room: Res<Room>,
+    local: Res<Player>,
+    mut panel: Query<&mut Visibility, With<CardCounterPanel>>,
+    mut cells: Query<(&mut Text, &CardCounterCell)>,
+    added: Query<(), Added<CardCounterPanel>>,
+) {
+    if !room.is_changed() && added.is_empty() {
+        return;
+    }
+    let counts = room
+        .game
+        .as_ref()
+        .filter(|_| room.rules.card_counter)
+        .and_then(|game| {
+            let seat = room.seat_of(local.id)?;
+            Some(remaining_counts(game, &game.hands[seat]))
+        });
+    for mut visibility in panel.iter_mut() {
+        *visibility = if counts.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+    let Some(counts) = counts else {
+        return;
+    };
+    for (mut text, cell) in cells.iter_mut() {
+        let Some(index) = COUNTER_RANKS.iter().position(|rank| *rank == cell.0) else {
+            continue;
+        };
+        text.sections[0].value = counts[index].to_string();
+        text.sections[0].style.color = if counts[index] == 0 {
+            Color::GRAY
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{card_deck::Suit, game::PlayRecord, room::RoomRules};
+
+    #[test]
+    fn excludes_plays_and_own_hand() {
+        let mut game = Game::new(RoomRules::default(), 0);
+        assert_eq!(
+            remaining_counts(&game, &[]),
+            [1, 1, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4]
+        );
+        game.plays = vec![
+            PlayRecord {
+                seat: 1,
+                cards: vec![
+                    Card::new(Suit::Spade, Rank::Three),
+                    Card::new(Suit::Heart, Rank::Three),
+                ],
+                pattern: None,
+                substitutes: vec![],
+            },
+            PlayRecord {
+                seat: 2,
+                cards: vec![Card::new(Suit::Joker, Rank::BigJoker)],
+                pattern: None,
+                substitutes: vec![],
+            },
+        ];
+        let hand = [
+            Card::new(Suit::Club, Rank::Three),
+            Card::new(Suit::Club, Rank::Two),
+        ];
+        let counts = remaining_counts(&game, &hand);
+        assert_eq!(counts[0], 0);
+        assert_eq!(counts[1], 1);
+        assert_eq!(counts[2], 3);
+        assert_eq!(counts[14], 1);
+    }
+}
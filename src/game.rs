@@ -0,0 +1,1093 @@
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::Card,
+    card_deck::{CardDeck, Rank, RANKS},
+    pattern::{Interpretation, Pattern, PatternRules},
+    room::{BidStyle, RoomRules},
+};
+
+// 叫分的最高分 叫到最高分直接成为地主
+pub const MAX_BID_SCORE: u8 = 3;
+
+// 开局前明牌的翻倍
+pub const SHOW_HAND_START_MULTIPLE: u32 = 4;
+// 发牌后叫地主时明牌的翻倍
+pub const SHOW_HAND_MULTIPLE: u32 = 2;
+
+// 不洗牌玩法每次发给一个人的张数
+const NO_SHUFFLE_CHUNK: usize = 3;
+
+// 一局游戏的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    /**叫地主 */
+    Bidding,
+    /**加倍 农民先选 地主最后 */
+    Doubling,
+    /**出牌 */
+    Playing,
+    /**结束 */
+    Finished,
+}
+
+// 叫地主阶段的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bid {
+    /**不叫/不抢 */
+    Pass,
+    /**叫分 */
+    Score(u8),
+    /**叫地主/抢地主 */
+    Rob,
+}
+
+impl Bid {
+    // 按钮和头像上显示的文字 called表示此前是否已经有人叫过地主
+    pub fn label(&self, called: bool) -> String {
+        match (self, called) {
+            (Bid::Pass, false) => "不叫".to_string(),
+            (Bid::Pass, true) => "不抢".to_string(),
+            (Bid::Score(score), _) => format!("{}分", score),
+            (Bid::Rob, false) => "叫地主".to_string(),
+            (Bid::Rob, true) => "抢地主".to_string(),
+        }
+    }
+}
+
+// 加倍阶段的选择
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Doubling {
+    /**不加倍 */
+    No,
+    /**加倍 */
+    Double,
+    /**超级加倍 */
+    Super,
+}
+
+impl Doubling {
+    pub const ALL: [Doubling; 3] = [Doubling::No, Doubling::Double, Doubling::Super];
+
+    pub fn factor(&self) -> u32 {
+        match self {
+            Doubling::No => 1,
+            Doubling::Double => 2,
+            Doubling::Super => 4,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Doubling::No => "不加倍",
+            Doubling::Double => "加倍",
+            Doubling::Super => "超级加倍",
+        }
+    }
+}
+
+// 玩家在一局中的操作 由房主校验后执行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GameAction {
+    Bid(Bid),
+    Double(Doubling),
+    Play(Vec<Card>),
+    Pass,
+    // 明牌 所有人都能看到自己的手牌
+    ShowHand,
+}
+
+// 出牌记录 pattern为None表示不出
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayRecord {
+    pub seat: usize,
+    pub cards: Vec<Card>,
+    pub pattern: Option<Pattern>,
+    // 癞子代替的点数
+    pub substitutes: Vec<(Card, Rank)>,
+}
+
+// 一局的结算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameResult {
+    // 最先出完牌的座位
+    pub winner: usize,
+    pub landlord_won: bool,
+    // 春天或反春
+    pub spring: bool,
+    // 最终倍数
+    pub multiple: u32,
+    // 每个座位的得分
+    pub scores: Vec<i32>,
+    // 每个座位输赢的金币 由房间按场次结算
+    pub coins: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameError {
+    // 不是该玩家行动
+    NotYourTurn,
+    // 当前阶段不能执行该操作
+    WrongPhase,
+    // 不合规则的叫分或抢地主
+    InvalidBid,
+    // 手里没有这些牌
+    NotInHand,
+    // 不是合法的牌型
+    InvalidPattern,
+    // 压不过上家
+    CannotBeat,
+    // 首家必须出牌
+    MustPlay,
+    // 没有开启明牌 已经明牌或者过了明牌的时机
+    CannotShowHand,
+}
+
+impl std::fmt::Display for GameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameError::NotYourTurn => write!(f, "还没轮到你"),
+            GameError::WrongPhase => write!(f, "当前不能这样操作"),
+            GameError::InvalidBid => write!(f, "不能这样叫地主"),
+            GameError::NotInHand => write!(f, "手里没有这些牌"),
+            GameError::InvalidPattern => write!(f, "不符合出牌规则"),
+            GameError::CannotBeat => write!(f, "压不过上家"),
+            GameError::MustPlay => write!(f, "你必须出牌"),
+            GameError::CannotShowHand => write!(f, "现在不能明牌"),
+        }
+    }
+}
+
+// 一局游戏 座位下标与房间座位一致
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Game {
+    // 开局时的房间规则
+    pub rules: RoomRules,
+    pub hands: Vec<Vec<Card>>,
+    // 底牌 确定地主后归地主
+    pub bottom: Vec<Card>,
+    pub phase: Phase,
+    // 当前行动的座位
+    pub turn: usize,
+    // 第一个叫地主的座位
+    pub first: usize,
+    pub landlord: Option<usize>,
+    // 本轮叫地主的记录
+    pub bids: Vec<(usize, Bid)>,
+    // 倍数 叫分为所叫分数 抢地主每抢一次翻倍 明牌、炸弹和春天再翻倍 癞子玩法中硬炸翻四倍
+    // 结算时不超过房间规则的封顶倍数
+    pub multiple: u32,
+    // 出牌记录
+    pub plays: Vec<PlayRecord>,
+    // 当前这一轮在出牌记录中开始的位置
+    pub trick_start: usize,
+    pub result: Option<GameResult>,
+    // 最近一次发牌的洗牌种子
+    pub seed: u64,
+    // 癞子玩法中发牌后翻出的癞子
+    pub laizi: Option<Rank>,
+    // 不洗牌玩法中切牌前的牌堆 为空表示洗牌 与种子一起可以还原发牌顺序
+    pub stack: Vec<Card>,
+    // 每个座位明牌的翻倍 None为没有明牌
+    pub shown: Vec<Option<u32>>,
+    // 最近一次发牌后叫地主阶段的明牌 (明牌时已经叫过的次数, 座位) 回放时按顺序插入
+    pub shows: Vec<(usize, usize)>,
+    // 每个座位加倍的选择 None为还没选
+    pub doubles: Vec<Option<Doubling>>,
+    // 最近一次发牌时每个座位的手牌 牌局结束时才发给其他客户端 用来录制回放
+    pub deal: Vec<Vec<Card>>,
+}
+
+impl Game {
+    pub fn new(rules: RoomRules, first: usize) -> Self {
+        Self::with_stack(rules, first, vec![])
+    }
+
+    // 用上一局收起的牌开局 牌堆为空或没有开启不洗牌时正常洗牌
+    pub fn with_stack(rules: RoomRules, first: usize, stack: Vec<Card>) -> Self {
+        let stack = if rules.no_shuffle { stack } else { vec![] };
+        let mut game = Self {
+            hands: vec![vec![]; rules.variant.seats()],
+            rules,
+            bottom: vec![],
+            phase: Phase::Bidding,
+            turn: first,
+            first,
+            landlord: None,
+            bids: vec![],
+            multiple: 1,
+            plays: vec![],
+            trick_start: 0,
+            result: None,
+            seed: 0,
+            laizi: None,
+            stack,
+            shown: vec![],
+            shows: vec![],
+            doubles: vec![],
+            deal: vec![],
+        };
+        game.shown = vec![None; game.seats()];
+        game.doubles = vec![None; game.seats()];
+        game.deal();
+        game
+    }
+
+    pub fn seats(&self) -> usize {
+        self.hands.len()
+    }
+
+    // 洗牌发牌 每人轮流发一张 最后留下底牌 种子保存下来用于复盘
+    // 不洗牌时在牌堆中间切一次 每次发几张
+    fn deal(&mut self) {
+        self.deal_with_seed(thread_rng().gen());
+    }
+
+    // 相同的种子和牌堆发出相同的牌
+    fn deal_with_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        let variant = self.rules.variant;
+        let (mut deck, chunk) = if self.stack.is_empty() {
+            let mut deck = CardDeck::with_decks(variant.decks());
+            deck.shuffle_with_seed(self.seed);
+            (deck, 1)
+        } else {
+            let mut deck = CardDeck::from_cards(self.stack.clone());
+            let len = self.stack.len();
+            deck.cut(len / 4 + (self.seed % (len / 2).max(1) as u64) as usize);
+            (deck, NO_SHUFFLE_CHUNK)
+        };
+        let (hands, bottom) = deck.deal_in_chunks(self.seats(), variant.bottom_cards(), chunk);
+        // 癞子也由种子决定 复盘时可以还原
+        self.laizi = self
+            .rules
+            .laizi
+            .then(|| RANKS[(self.seed % RANKS.len() as u64) as usize]);
+        self.hands = hands;
+        for hand in self.hands.iter_mut() {
+            hand.sort();
+        }
+        self.bottom = bottom;
+        self.deal = self.hands.clone();
+        // 重新发牌前的明牌算作发牌时就已明牌
+        self.shows.clear();
+    }
+
+    // 收起这一局的牌 按出牌顺序叠放 剩下的手牌按座位放在下面 没有地主时底牌放在最下面
+    pub fn gathered(&self) -> Vec<Card> {
+        let mut cards: Vec<Card> = self
+            .plays
+            .iter()
+            .flat_map(|record| record.cards.iter().copied())
+            .collect();
+        cards.extend(self.hands.iter().flatten().copied());
+        if self.landlord.is_none() {
+            cards.extend(self.bottom.iter().copied());
+        }
+        cards
+    }
+
+    // 执行玩家操作
+    pub fn apply(&mut self, seat: usize, action: &GameAction) -> Result<(), GameError> {
+        match action {
+            GameAction::Bid(bid) => self.bid(seat, *bid),
+            GameAction::Double(double) => self.double(seat, *double),
+            GameAction::Play(cards) => self.play(seat, cards),
+            GameAction::Pass => self.pass(seat),
+            GameAction::ShowHand => {
+                self.show_hand(seat, SHOW_HAND_MULTIPLE)?;
+                self.shows.push((self.bids.len(), seat));
+                Ok(())
+            }
+        }
+    }
+
+    // 明牌 开局前和叫地主阶段都可以 每人只能明牌一次
+    pub fn show_hand(&mut self, seat: usize, multiple: u32) -> Result<(), GameError> {
+        if !self.rules.show_hand
+            || self.phase != Phase::Bidding
+            || self.shown.get(seat) != Some(&None)
+        {
+            return Err(GameError::CannotShowHand);
+        }
+        self.shown[seat] = Some(multiple);
+        Ok(())
+    }
+
+    // 明牌的翻倍 多人明牌时取最大的
+    pub fn show_hand_multiple(&self) -> u32 {
+        self.shown.iter().flatten().copied().max().unwrap_or(1)
+    }
+
+    // viewer看到的seat的手牌 不是自己也没有明牌时为牌背
+    pub fn visible_hand(&self, seat: usize, viewer: Option<usize>) -> Vec<Card> {
+        let hide = viewer != Some(seat) && self.shown[seat].is_none();
+        self.hands[seat]
+            .iter()
+            .map(|card| Card { hide, ..*card })
+            .collect()
+    }
+
+    // 发给viewer的牌局 看不到的手牌换成牌背
+    // 牌局结束前底牌在确定地主后才公开 发牌记录、牌堆和种子可以还原发牌 也不发
+    pub fn masked(&self, viewer: Option<usize>) -> Self {
+        let mut game = self.clone();
+        if self.phase == Phase::Finished {
+            return game;
+        }
+        for (seat, hand) in game.hands.iter_mut().enumerate() {
+            if viewer != Some(seat) && self.shown[seat].is_none() {
+                *hand = vec![Card::BACK; hand.len()];
+            }
+        }
+        if self.landlord.is_none() {
+            game.bottom = vec![Card::BACK; self.bottom.len()];
+        }
+        game.seed = 0;
+        game.stack.clear();
+        game.deal.clear();
+        game
+    }
+
+    // 当前叫到的最高分
+    pub fn bid_score(&self) -> u8 {
+        self.bids
+            .iter()
+            .filter_map(|(_, bid)| match bid {
+                Bid::Score(score) => Some(*score),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    // 是否已经有人叫过地主
+    pub fn called(&self) -> bool {
+        self.bids.iter().any(|(_, bid)| *bid == Bid::Rob)
+    }
+
+    // 座位的叫地主操作在显示时是否算作抢地主
+    pub fn robbing(&self, index: usize) -> bool {
+        self.bids[..index].iter().any(|(_, bid)| *bid == Bid::Rob)
+    }
+
+    // 轮到的玩家可以选择的叫地主操作
+    pub fn bid_options(&self) -> Vec<Bid> {
+        let candidates = match self.rules.bid_style {
+            BidStyle::Score => (1..=MAX_BID_SCORE)
+                .map(Bid::Score)
+                .chain([Bid::Pass])
+                .collect(),
+            BidStyle::Rob => vec![Bid::Rob, Bid::Pass],
+        };
+        candidates
+            .into_iter()
+            .filter(|bid| self.check_bid(self.turn, *bid).is_ok())
+            .collect()
+    }
+
+    // 出牌超时时替当前玩家执行的操作 叫地主时不叫 能不出就不出 否则出最小的一张
+    pub fn timeout_action(&self) -> GameAction {
+        match self.phase {
+            Phase::Playing if !self.following() => {
+                let card = self.hands[self.turn]
+                    .iter()
+                    .min_by_key(|card| card.rank.value())
+                    .copied();
+                GameAction::Play(card.into_iter().collect())
+            }
+            Phase::Playing => GameAction::Pass,
+            Phase::Doubling => GameAction::Double(Doubling::No),
+            _ => GameAction::Bid(Bid::Pass),
+        }
+    }
+
+    // 校验当前轮到的玩家能否这样叫
+    pub fn check_bid(&self, seat: usize, bid: Bid) -> Result<(), GameError> {
+        if self.phase != Phase::Bidding {
+            return Err(GameError::WrongPhase);
+        }
+        if seat != self.turn {
+            return Err(GameError::NotYourTurn);
+        }
+        let valid = match (self.rules.bid_style, bid) {
+            (_, Bid::Pass) => true,
+            (BidStyle::Score, Bid::Score(score)) => {
+                score > self.bid_score() && score <= MAX_BID_SCORE
+            }
+            (BidStyle::Rob, Bid::Rob) => true,
+            _ => false,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(GameError::InvalidBid)
+        }
+    }
+
+    fn bid(&mut self, seat: usize, bid: Bid) -> Result<(), GameError> {
+        self.check_bid(seat, bid)?;
+        self.bids.push((seat, bid));
+        match self.next_bidder() {
+            Some(next) => self.turn = next,
+            None => self.finish_bidding(),
+        }
+        Ok(())
+    }
+
+    // 下一个叫地主的座位 没有则叫地主结束
+    fn next_bidder(&self) -> Option<usize> {
+        let seats = self.seats();
+        match self.rules.bid_style {
+            BidStyle::Score => {
+                // 叫到最高分或者每人都叫过一次
+                if self.bid_score() == MAX_BID_SCORE || self.bids.len() >= seats {
+                    None
+                } else {
+                    Some((self.turn + 1) % seats)
+                }
+            }
+            BidStyle::Rob => {
+                if self.bids.len() < seats {
+                    return Some((self.turn + 1) % seats);
+                }
+                // 每人一轮后 第一个叫地主的人被抢过 还有一次抢地主的机会
+                let callers = self.round_callers();
+                if self.bids.len() == seats && callers.len() > 1 {
+                    Some(callers[0])
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    // 第一轮中叫过或抢过地主的座位
+    fn round_callers(&self) -> Vec<usize> {
+        self.bids
+            .iter()
+            .take(self.seats())
+            .filter(|(_, bid)| *bid == Bid::Rob)
+            .map(|(seat, _)| *seat)
+            .collect()
+    }
+
+    fn finish_bidding(&mut self) {
+        let landlord = match self.rules.bid_style {
+            BidStyle::Score => self
+                .bids
+                .iter()
+                .filter(|(_, bid)| matches!(bid, Bid::Score(_)))
+                .max_by_key(|(_, bid)| match bid {
+                    Bid::Score(score) => *score,
+                    _ => 0,
+                })
+                .map(|(seat, _)| *seat),
+            BidStyle::Rob => self
+                .bids
+                .iter()
+                .rev()
+                .find(|(_, bid)| *bid == Bid::Rob)
+                .map(|(seat, _)| *seat),
+        };
+        let Some(landlord) = landlord else {
+            // 没人叫地主 下一位先叫 重新发牌
+            self.first = (self.first + 1) % self.seats();
+            self.turn = self.first;
+            self.bids.clear();
+            if self.rules.no_shuffle {
+                self.stack = self.gathered();
+            }
+            self.deal();
+            return;
+        };
+        self.multiple = match self.rules.bid_style {
+            BidStyle::Score => self.bid_score() as u32,
+            // 叫地主之后每抢一次翻倍
+            BidStyle::Rob => {
+                let robs = self.bids.iter().filter(|(_, bid)| *bid == Bid::Rob).count();
+                1 << (robs - 1)
+            }
+        };
+        self.multiple *= self.show_hand_multiple();
+        self.landlord = Some(landlord);
+        self.hands[landlord].extend(self.bottom.iter().copied());
+        self.hands[landlord].sort();
+        // 开启加倍时从地主的下家开始选 否则地主直接出牌
+        if self.rules.doubling {
+            self.turn = (landlord + 1) % self.seats();
+            self.phase = Phase::Doubling;
+        } else {
+            self.turn = landlord;
+            self.phase = Phase::Playing;
+        }
+    }
+
+    fn double(&mut self, seat: usize, double: Doubling) -> Result<(), GameError> {
+        if self.phase != Phase::Doubling {
+            return Err(GameError::WrongPhase);
+        }
+        if seat != self.turn {
+            return Err(GameError::NotYourTurn);
+        }
+        self.doubles[seat] = Some(double);
+        let landlord = self.landlord.unwrap_or(seat);
+        // 农民按座位顺序选完后轮到地主 地主选完开始出牌
+        self.turn = if seat == landlord {
+            self.phase = Phase::Playing;
+            landlord
+        } else {
+            (seat + 1) % self.seats()
+        };
+        Ok(())
+    }
+
+    // 座位的加倍翻倍 没有选或没有加倍阶段时为1
+    pub fn double_factor(&self, seat: usize) -> u32 {
+        self.doubles[seat].map_or(1, |double| double.factor())
+    }
+
+    // 当前这一轮的出牌记录 新一轮开始时桌面清空
+    pub fn trick(&self) -> &[PlayRecord] {
+        &self.plays[self.trick_start..]
+    }
+
+    // 这一轮最后一手出的牌
+    pub fn last_play(&self) -> Option<&PlayRecord> {
+        self.trick()
+            .iter()
+            .rev()
+            .find(|record| record.pattern.is_some())
+    }
+
+    // 是否需要压上家的牌 自己是这一轮的首家时不需要
+    pub fn following(&self) -> bool {
+        self.last_play().is_some()
+    }
+
+    pub fn pattern_rules(&self) -> PatternRules {
+        PatternRules {
+            decks: self.rules.variant.decks(),
+            laizi: self.laizi,
+            four_with_two_pairs: self.rules.four_with_two_pairs,
+            bomb_wings: self.rules.bomb_wings,
+        }
+    }
+
+    // 校验出牌 返回牌型和癞子的代替方式
+    // 跟牌时优先按上家的牌型识别 首家出牌时取最大的一种
+    pub fn check_play(&self, seat: usize, cards: &[Card]) -> Result<Interpretation, GameError> {
+        if self.phase != Phase::Playing {
+            return Err(GameError::WrongPhase);
+        }
+        if seat != self.turn {
+            return Err(GameError::NotYourTurn);
+        }
+        let mut hand = self.hands[seat].clone();
+        for card in cards {
+            let index = hand
+                .iter()
+                .position(|c| c == card)
+                .ok_or(GameError::NotInHand)?;
+            hand.remove(index);
+        }
+        let interpretations = Pattern::interpretations(cards, &self.pattern_rules());
+        if interpretations.is_empty() {
+            return Err(GameError::InvalidPattern);
+        }
+        match self.last_play().and_then(|record| record.pattern) {
+            Some(last) => interpretations
+                .into_iter()
+                .filter(|interpretation| interpretation.pattern.beats(&last))
+                .max_by_key(|interpretation| {
+                    (
+                        interpretation.pattern.kind == last.kind,
+                        interpretation.pattern.strength(),
+                    )
+                })
+                .ok_or(GameError::CannotBeat),
+            None => interpretations
+                .into_iter()
+                .max_by_key(|interpretation| interpretation.pattern.strength())
+                .ok_or(GameError::InvalidPattern),
+        }
+    }
+
+    fn play(&mut self, seat: usize, cards: &[Card]) -> Result<(), GameError> {
+        let Interpretation {
+            pattern,
+            substitutes,
+        } = self.check_play(seat, cards)?;
+        for card in cards {
+            if let Some(index) = self.hands[seat].iter().position(|c| c == card) {
+                self.hands[seat].remove(index);
+            }
+        }
+        self.multiple *= pattern.multiple(self.laizi.is_some());
+        self.plays.push(PlayRecord {
+            seat,
+            cards: cards.to_vec(),
+            pattern: Some(pattern),
+            substitutes,
+        });
+        if self.hands[seat].is_empty() {
+            self.settle(seat);
+        } else {
+            self.turn = (seat + 1) % self.seats();
+        }
+        Ok(())
+    }
+
+    fn pass(&mut self, seat: usize) -> Result<(), GameError> {
+        if self.phase != Phase::Playing {
+            return Err(GameError::WrongPhase);
+        }
+        if seat != self.turn {
+            return Err(GameError::NotYourTurn);
+        }
+        let Some(leader) = self.last_play().map(|record| record.seat) else {
+            return Err(GameError::MustPlay);
+        };
+        self.plays.push(PlayRecord {
+            seat,
+            cards: vec![],
+            pattern: None,
+            substitutes: vec![],
+        });
+        self.turn = (seat + 1) % self.seats();
+        // 其他人都不要 出牌的人开始新的一轮
+        if self.turn == leader {
+            self.trick_start = self.plays.len();
+        }
+        Ok(())
+    }
+
+    // 出完牌结算 地主输赢的分数由每个农民分担
+    fn settle(&mut self, winner: usize) {
+        let landlord = self.landlord.unwrap_or(winner);
+        let landlord_won = winner == landlord;
+        let landlord_plays = self
+            .plays
+            .iter()
+            .filter(|record| record.seat == landlord && record.pattern.is_some())
+            .count();
+        let farmer_plays = self
+            .plays
+            .iter()
+            .filter(|record| record.seat != landlord && record.pattern.is_some())
+            .count();
+        // 春天: 农民一张没出 反春: 地主只出了第一手
+        let spring = if landlord_won {
+            farmer_plays == 0
+        } else {
+            landlord_plays == 1
+        };
+        if spring {
+            self.multiple *= 2;
+        }
+        // 地主和每个农民分别结算 倍数再乘上双方各自的加倍 超过封顶按封顶计算
+        let cap = self.rules.max_multiple.unwrap_or(u32::MAX);
+        let sign = if landlord_won { 1 } else { -1 };
+        let mut scores = vec![0; self.seats()];
+        for farmer in (0..self.seats()).filter(|seat| *seat != landlord) {
+            let multiple =
+                (self.multiple * self.double_factor(farmer) * self.double_factor(landlord))
+                    .min(cap);
+            let score = sign * (self.rules.base_score * multiple) as i32;
+            scores[landlord] += score;
+            scores[farmer] -= score;
+        }
+        self.multiple = self.multiple.min(cap);
+        self.result = Some(GameResult {
+            winner,
+            landlord_won,
+            spring,
+            multiple: self.multiple,
+            scores,
+            coins: vec![0; self.seats()],
+        });
+        self.phase = Phase::Finished;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::tests::cards;
+
+    fn rules(bid_style: BidStyle) -> RoomRules {
+        RoomRules {
+            bid_style,
+            ..Default::default()
+        }
+    }
+
+    // 当前轮到的座位叫地主
+    fn bid(game: &mut Game, bid: Bid) {
+        let seat = game.turn;
+        game.apply(seat, &GameAction::Bid(bid)).unwrap();
+    }
+
+    #[test]
+    fn score_bidding_takes_highest_score() {
+        let mut game = Game::new(rules(BidStyle::Score), 0);
+        bid(&mut game, Bid::Score(1));
+        assert_eq!(game.check_bid(1, Bid::Score(1)), Err(GameError::InvalidBid));
+        assert_eq!(
+            game.check_bid(2, Bid::Score(2)),
+            Err(GameError::NotYourTurn)
+        );
+        assert_eq!(game.check_bid(1, Bid::Rob), Err(GameError::InvalidBid));
+        bid(&mut game, Bid::Score(2));
+        bid(&mut game, Bid::Pass);
+        assert_eq!(game.phase, Phase::Playing);
+        assert_eq!(game.landlord, Some(1));
+        assert_eq!(game.turn, 1);
+        assert_eq!(game.multiple, 2);
+        // 地主拿到底牌
+        assert_eq!(game.hands[1].len(), 20);
+        assert!(game.bottom.iter().all(|card| game.hands[1].contains(card)));
+    }
+
+    #[test]
+    fn max_score_ends_bidding() {
+        let mut game = Game::new(rules(BidStyle::Score), 1);
+        bid(&mut game, Bid::Score(MAX_BID_SCORE));
+        assert_eq!(game.phase, Phase::Playing);
+        assert_eq!(game.landlord, Some(1));
+        assert_eq!(game.multiple, MAX_BID_SCORE as u32);
+        assert_eq!(game.bids.len(), 1);
+    }
+
+    #[test]
+    fn rob_bidding_doubles_per_rob() {
+        // 只有一人叫地主 不用再抢
+        let mut game = Game::new(rules(BidStyle::Rob), 0);
+        bid(&mut game, Bid::Rob);
+        bid(&mut game, Bid::Pass);
+        bid(&mut game, Bid::Pass);
+        assert_eq!(game.landlord, Some(0));
+        assert_eq!(game.multiple, 1);
+
+        // 被抢过之后第一个叫地主的人还能再抢一次
+        let mut game = Game::new(rules(BidStyle::Rob), 0);
+        bid(&mut game, Bid::Rob);
+        bid(&mut game, Bid::Rob);
+        bid(&mut game, Bid::Pass);
+        assert_eq!(game.phase, Phase::Bidding);
+        assert_eq!(game.turn, 0);
+        bid(&mut game, Bid::Rob);
+        assert_eq!(game.landlord, Some(0));
+        assert_eq!(game.multiple, 4);
+
+        // 放弃再抢时最后抢的人当地主
+        let mut game = Game::new(rules(BidStyle::Rob), 0);
+        bid(&mut game, Bid::Rob);
+        bid(&mut game, Bid::Pass);
+        bid(&mut game, Bid::Rob);
+        assert_eq!(game.turn, 0);
+        bid(&mut game, Bid::Pass);
+        assert_eq!(game.landlord, Some(2));
+        assert_eq!(game.multiple, 2);
+    }
+
+    #[test]
+    fn redeals_when_nobody_bids() {
+        let mut game = Game::new(rules(BidStyle::Score), 0);
+        for _ in 0..game.seats() {
+            bid(&mut game, Bid::Pass);
+        }
+        // 下一位先叫 重新发牌
+        assert_eq!(game.phase, Phase::Bidding);
+        assert_eq!(game.landlord, None);
+        assert!(game.bids.is_empty());
+        assert_eq!(game.first, 1);
+        assert_eq!(game.turn, 1);
+        assert!(game.hands.iter().all(|hand| hand.len() == 17));
+        assert_eq!(game.bottom.len(), 3);
+    }
+
+    // 打完一局 叫1分后所有人超时托管
+    fn play_out(game: &mut Game) {
+        while game.phase != Phase::Finished {
+            let action = match game.phase {
+                Phase::Bidding => GameAction::Bid(game.bid_options()[0]),
+                _ => game.timeout_action(),
+            };
+            game.apply(game.turn, &action).unwrap();
+        }
+    }
+
+    fn sorted(mut cards: Vec<Card>) -> Vec<Card> {
+        cards.sort();
+        cards
+    }
+
+    #[test]
+    fn no_shuffle_deal_is_deterministic() {
+        let rules = RoomRules {
+            no_shuffle: true,
+            ..Default::default()
+        };
+        let stack = CardDeck::with_decks(1).cards().to_vec();
+        let mut game = Game::with_stack(rules.clone(), 0, stack.clone());
+        let mut other = Game::with_stack(rules, 0, stack.clone());
+        game.deal_with_seed(5);
+        other.deal_with_seed(5);
+        assert_eq!(game.hands, other.hands);
+        assert_eq!(game.bottom, other.bottom);
+        // 在54/4 + 5%27 = 18张处切牌 每人每次连续发3张 最后3张是底牌
+        for (seat, hand) in game.hands.iter().enumerate() {
+            let start = 18 + seat * NO_SHUFFLE_CHUNK;
+            assert!(stack[start..start + NO_SHUFFLE_CHUNK]
+                .iter()
+                .all(|card| hand.contains(card)));
+        }
+        assert_eq!(game.bottom, stack[15..18]);
+    }
+
+    #[test]
+    fn no_shuffle_carries_previous_hand() {
+        let rules = RoomRules {
+            no_shuffle: true,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules.clone(), 0);
+        play_out(&mut game);
+        let gathered = game.gathered();
+        assert_eq!(gathered.len(), 54);
+        let next = Game::with_stack(rules, 1, gathered.clone());
+        assert_eq!(next.stack, gathered);
+        let mut dealt = next.hands.concat();
+        dealt.extend(next.bottom.iter().copied());
+        assert_eq!(sorted(dealt), sorted(gathered.clone()));
+
+        // 没开不洗牌时忽略牌堆
+        let shuffled = Game::with_stack(RoomRules::default(), 1, gathered);
+        assert!(shuffled.stack.is_empty());
+    }
+
+    #[test]
+    fn timeout_passes_or_leads_lowest_card() {
+        let mut game = Game::new(
+            RoomRules {
+                doubling: true,
+                ..Default::default()
+            },
+            0,
+        );
+        assert!(matches!(game.timeout_action(), GameAction::Bid(Bid::Pass)));
+        bid(&mut game, Bid::Score(MAX_BID_SCORE));
+        assert!(matches!(
+            game.timeout_action(),
+            GameAction::Double(Doubling::No)
+        ));
+        for _ in 0..game.seats() {
+            let action = game.timeout_action();
+            game.apply(game.turn, &action).unwrap();
+        }
+        assert!(game
+            .doubles
+            .iter()
+            .all(|double| *double == Some(Doubling::No)));
+        // 地主先出 超时出最小的一张
+        let lowest = game.hands[0].iter().map(|card| card.rank.value()).min();
+        let GameAction::Play(cards) = game.timeout_action() else {
+            panic!("首出不能不出");
+        };
+        assert_eq!(cards.len(), 1);
+        assert_eq!(Some(cards[0].rank.value()), lowest);
+        game.apply(0, &GameAction::Play(cards)).unwrap();
+        // 跟牌时超时不出
+        assert!(matches!(game.timeout_action(), GameAction::Pass));
+    }
+
+    #[test]
+    fn show_hand_multiplies_bid() {
+        let rules = RoomRules {
+            show_hand: true,
+            ..Default::default()
+        };
+        // 规则没开时不能明牌
+        let mut game = Game::new(RoomRules::default(), 0);
+        assert_eq!(
+            game.show_hand(0, SHOW_HAND_MULTIPLE),
+            Err(GameError::CannotShowHand)
+        );
+
+        let mut game = Game::new(rules, 0);
+        assert_eq!(game.show_hand_multiple(), 1);
+        game.show_hand(1, SHOW_HAND_START_MULTIPLE).unwrap();
+        bid(&mut game, Bid::Score(1));
+        // 叫地主阶段不用等轮到自己也能明牌
+        game.apply(2, &GameAction::ShowHand).unwrap();
+        // 每人只能明牌一次 多人明牌取最大的翻倍
+        assert_eq!(
+            game.show_hand(1, SHOW_HAND_MULTIPLE),
+            Err(GameError::CannotShowHand)
+        );
+        assert_eq!(game.show_hand_multiple(), SHOW_HAND_START_MULTIPLE);
+        // 明牌的手牌对所有人可见
+        assert!(game.visible_hand(1, Some(0)).iter().all(|card| !card.hide));
+        assert!(game.visible_hand(0, Some(1)).iter().all(|card| card.hide));
+        assert!(game.visible_hand(0, Some(0)).iter().all(|card| !card.hide));
+        bid(&mut game, Bid::Score(2));
+        bid(&mut game, Bid::Pass);
+        assert_eq!(game.landlord, Some(1));
+        assert_eq!(game.multiple, 2 * SHOW_HAND_START_MULTIPLE);
+        // 叫完地主后不能再明牌
+        assert_eq!(
+            game.show_hand(0, SHOW_HAND_MULTIPLE),
+            Err(GameError::CannotShowHand)
+        );
+    }
+
+    #[test]
+    fn masks_hands_the_viewer_cannot_see() {
+        let rules = RoomRules {
+            show_hand: true,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules, 0);
+        game.show_hand(1, SHOW_HAND_START_MULTIPLE).unwrap();
+        let view = game.masked(Some(0));
+        // 自己和明牌的手牌照常发 其他手牌和底牌只有牌背
+        assert_eq!(view.hands[0], game.hands[0]);
+        assert_eq!(view.hands[1], game.hands[1]);
+        assert_eq!(view.hands[2], vec![Card::BACK; game.hands[2].len()]);
+        assert_eq!(view.bottom, vec![Card::BACK; game.bottom.len()]);
+        assert_eq!(view.seed, 0);
+        assert!(view.deal.is_empty());
+        // 没有座位时只能看到明牌的手牌
+        assert!(game.masked(None).hands[0].iter().all(|card| card.hide));
+
+        bid(&mut game, Bid::Score(MAX_BID_SCORE));
+        assert_eq!(game.masked(Some(1)).bottom, game.bottom);
+        play_out(&mut game);
+        assert_eq!(game.masked(Some(1)).hands, game.hands);
+        assert_eq!(game.masked(Some(1)).deal, game.deal);
+    }
+
+    // 从手牌中按点数出牌 不管花色
+    fn play(game: &mut Game, seat: usize, ranks: &str) {
+        let mut hand = game.hands[seat].clone();
+        let played = cards(ranks)
+            .iter()
+            .map(|card| {
+                let index = hand.iter().position(|c| c.rank == card.rank).unwrap();
+                hand.remove(index)
+            })
+            .collect();
+        game.apply(seat, &GameAction::Play(played)).unwrap();
+    }
+
+    #[test]
+    fn farmers_double_before_landlord() {
+        let mut game = Game::new(
+            RoomRules {
+                doubling: true,
+                ..Default::default()
+            },
+            0,
+        );
+        bid(&mut game, Bid::Pass);
+        bid(&mut game, Bid::Score(MAX_BID_SCORE));
+        assert_eq!(game.phase, Phase::Doubling);
+        // 从地主下家开始 地主最后
+        assert_eq!(game.turn, 2);
+        assert_eq!(
+            game.apply(1, &GameAction::Double(Doubling::Super)),
+            Err(GameError::NotYourTurn)
+        );
+        game.apply(2, &GameAction::Double(Doubling::Double))
+            .unwrap();
+        game.apply(0, &GameAction::Double(Doubling::Super)).unwrap();
+        assert_eq!(game.phase, Phase::Doubling);
+        assert_eq!(game.turn, 1);
+        game.apply(1, &GameAction::Double(Doubling::No)).unwrap();
+        assert_eq!(game.phase, Phase::Playing);
+        assert_eq!(game.turn, 1);
+        assert_eq!(
+            (0..3)
+                .map(|seat| game.double_factor(seat))
+                .collect::<Vec<_>>(),
+            vec![4, 1, 2]
+        );
+        assert_eq!(
+            game.apply(1, &GameAction::Double(Doubling::Double)),
+            Err(GameError::WrongPhase)
+        );
+    }
+
+    // 座位0叫3分当地主 按给定的加倍进入出牌
+    fn landlord_game(rules: RoomRules, doubles: [Doubling; 3]) -> Game {
+        let mut game = Game::new(rules, 0);
+        bid(&mut game, Bid::Score(MAX_BID_SCORE));
+        game.doubles = doubles.into_iter().map(Some).collect();
+        game.phase = Phase::Playing;
+        game.turn = 0;
+        game
+    }
+
+    #[test]
+    fn settles_each_farmer_with_doubles_and_spring() {
+        let rules = RoomRules {
+            base_score: 2,
+            ..Default::default()
+        };
+        let doubles = [Doubling::Double, Doubling::Super, Doubling::No];
+        // 地主一手出完 农民一张没出 春天翻倍
+        let mut game = landlord_game(rules.clone(), doubles);
+        game.hands[0] = cards("3");
+        play(&mut game, 0, "3");
+        let result = game.result.clone().unwrap();
+        assert!(result.landlord_won);
+        assert!(result.spring);
+        assert_eq!(result.multiple, 6);
+        // 每个农民按 底分 × 倍数 × 农民加倍 × 地主加倍 结算
+        assert_eq!(result.scores, vec![96 + 24, -96, -24]);
+
+        // 超过封顶按封顶算
+        let mut game = landlord_game(
+            RoomRules {
+                max_multiple: Some(32),
+                ..rules
+            },
+            doubles,
+        );
+        game.hands[0] = cards("3");
+        play(&mut game, 0, "3");
+        let result = game.result.unwrap();
+        assert_eq!(result.scores, vec![64 + 24, -64, -24]);
+    }
+
+    #[test]
+    fn settles_farmer_win_without_spring() {
+        let mut game = landlord_game(RoomRules::default(), [Doubling::No; 3]);
+        game.hands = vec![cards("349K"), cards("56"), cards("789")];
+        play(&mut game, 0, "3");
+        play(&mut game, 1, "5");
+        game.apply(2, &GameAction::Pass).unwrap();
+        play(&mut game, 0, "K");
+        game.apply(1, &GameAction::Pass).unwrap();
+        game.apply(2, &GameAction::Pass).unwrap();
+        play(&mut game, 0, "4");
+        play(&mut game, 1, "6");
+        let result = game.result.unwrap();
+        assert_eq!(result.winner, 1);
+        assert!(!result.landlord_won);
+        assert!(!result.spring);
+        assert_eq!(result.multiple, 3);
+        assert_eq!(result.scores, vec![-6, 3, 3]);
+
+        // 地主只出了第一手 反春
+        let mut game = landlord_game(RoomRules::default(), [Doubling::No; 3]);
+        game.hands = vec![cards("34"), cards("5"), cards("789")];
+        play(&mut game, 0, "3");
+        play(&mut game, 1, "5");
+        let result = game.result.unwrap();
+        assert!(result.spring);
+        assert_eq!(result.scores, vec![-12, 6, 6]);
+    }
+}
@@ -0,0 +1,499 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::{get_sprite_index, Card, CardAtlas},
+    common::{on_table, AppMode, AppState, MyAssets},
+    pattern::MAX_CHAIN_VALUE,
+    player::Player,
+    room::{Room, RoomUIComponent},
+    storage,
+};
+
+// 手牌缩放
+const CARD_SCALE: f32 = 0.8;
+// 单张牌的尺寸
+const CARD_SIZE: Vec2 = Vec2::new(116. * CARD_SCALE, 159. * CARD_SCALE);
+// 相邻两张牌的最大间距
+const CARD_SPACING: f32 = 40.;
+// 手牌区域最大宽度
+const HAND_MAX_WIDTH: f32 = 900.;
+// 手牌距离窗口底部的距离
+const HAND_BOTTOM_MARGIN: f32 = 16.;
+// 选中的牌抬起的高度
+const SELECTED_OFFSET: f32 = 24.;
+// 双击的时间间隔
+const DOUBLE_CLICK_SECS: f32 = 0.3;
+// 双击时两次点击最多相距的像素 快速点两张不同的牌不算双击
+const DOUBLE_CLICK_DISTANCE: f32 = 4.;
+// 保存排列方式的键
+const HAND_SORT_KEY: &str = "hand_sort";
+
+pub struct HandPlugin;
+
+// 自己的手牌 index为从左到右的位置
+#[derive(Component)]
+pub struct HandCard {
+    pub index: usize,
+    pub selected: bool,
+}
+
+// 当前显示的手牌 与牌局中的手牌不同时重建
+#[derive(Resource, Default)]
+pub struct LocalHand(pub Vec<Card>);
+
+// 鼠标拖动选牌 按下时的牌和当前经过的牌
+#[derive(Resource, Default)]
+pub struct HandDrag {
+    pub start: Option<usize>,
+    pub current: usize,
+    // 上一次点击的时间和位置 用于判断双击
+    pub last_click: Option<(f32, Vec2)>,
+}
+
+// 手牌排列方式 保存在本地 下次启动沿用
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HandSort {
+    /**按点数 大王、小王、2、A到3 */
+    #[default]
+    Rank,
+    /**按张数 炸弹、三张、对子在前 */
+    Count,
+    /**理牌 王炸、炸弹、飞机、顺子各自放在一起 */
+    Arrange,
+}
+
+impl HandSort {
+    pub fn label(&self) -> &'static str {
+        match self {
+            HandSort::Rank => "点数",
+            HandSort::Count => "张数",
+            HandSort::Arrange => "理牌",
+        }
+    }
+
+    // 点击排序按钮后的方式
+    pub fn next(&self) -> Self {
+        match self {
+            HandSort::Rank => HandSort::Count,
+            HandSort::Count => HandSort::Arrange,
+            HandSort::Arrange => HandSort::Rank,
+        }
+    }
+}
+
+// 切换排列方式的按钮
+#[derive(Component)]
+pub struct HandSortButton;
+
+impl HandDrag {
+    // 拖动经过的范围
+    fn range(&self) -> Option<std::ops::RangeInclusive<usize>> {
+        self.start
+            .map(|start| start.min(self.current)..=start.max(self.current))
+    }
+}
+
+impl Plugin for HandPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LocalHand>()
+            .init_resource::<HandDrag>()
+            .insert_resource(storage::load::<HandSort>(HAND_SORT_KEY).unwrap_or_default())
+            .add_systems(
+                OnTransition {
+                    from: AppState::Lobby,
+                    to: AppState::InRoom,
+                },
+                setup.run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                OnEnter(AppState::Replay),
+                setup.run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                Update,
+                sort_button
+                    .before(refresh_hand)
+                    .run_if(on_table)
+                    .run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                Update,
+                (refresh_hand, select_cards, layout_hand)
+                    .chain()
+                    .run_if(on_table)
+                    .run_if(resource_exists::<CardAtlas>()),
+            )
+            .add_systems(OnEnter(AppState::Lobby), clear_hand);
+    }
+}
+
+fn setup(mut commands: Commands, assets: Res<MyAssets>, sort: Res<HandSort>) {
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Percent(2.),
+                    right: Val::Percent(2.),
+                    padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                ..Default::default()
+            },
+            HandSortButton,
+            RoomUIComponent,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                format!("排序: {}", sort.label()),
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: 22.,
+                    color: Color::WHITE,
+                },
+            ));
+        });
+}
+
+// 点击切换排列方式并保存
+fn sort_button(
+    mut sort: ResMut<HandSort>,
+    query: Query<(&Interaction, &Children), (Changed<Interaction>, With<HandSortButton>)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (interaction, children) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        *sort = sort.next();
+        storage::save(HAND_SORT_KEY, &*sort);
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                text.sections[0].value = format!("排序: {}", sort.label());
+            }
+        }
+    }
+}
+
+fn clear_hand(mut hand: ResMut<LocalHand>, mut drag: ResMut<HandDrag>) {
+    hand.0.clear();
+    *drag = HandDrag::default();
+}
+
+// 按斗地主的大小从大到小排列 点数相同按花色
+pub fn sort_by_rank(cards: &mut [Card]) {
+    cards.sort_by_key(|card| Reverse((card.rank.value(), card.suit)));
+}
+
+// 按排列方式整理手牌 从左到右
+pub fn sort_hand(cards: &[Card], sort: HandSort) -> Vec<Card> {
+    let mut cards = cards.to_vec();
+    sort_by_rank(&mut cards);
+    match sort {
+        HandSort::Rank => cards,
+        HandSort::Count => {
+            let count = |card: &Card| cards.iter().filter(|c| c.rank == card.rank).count();
+            let mut sorted = cards.clone();
+            sorted.sort_by_key(|card| Reverse((count(card), card.rank.value(), card.suit)));
+            sorted
+        }
+        HandSort::Arrange => arrange(cards),
+    }
+}
+
+// 取出某个点数的n张牌
+fn take(pool: &mut BTreeMap<u8, Vec<Card>>, value: u8, n: usize) -> Vec<Card> {
+    let Some(cards) = pool.get_mut(&value) else {
+        return vec![];
+    };
+    let taken: Vec<Card> = cards.drain(..n.min(cards.len())).collect();
+    if cards.is_empty() {
+        pool.remove(&value);
+    }
+    taken
+}
+
+// 至少有min张的点数中最长的一段连续 长度相同取大的 不能连到2和王
+fn longest_run(pool: &BTreeMap<u8, Vec<Card>>, min: usize) -> Vec<u8> {
+    let mut best: Vec<u8> = vec![];
+    let mut run: Vec<u8> = vec![];
+    for (value, cards) in pool.iter() {
+        if cards.len() < min || *value > MAX_CHAIN_VALUE {
+            continue;
+        }
+        if run.last().is_some_and(|last| last + 1 != *value) {
+            run.clear();
+        }
+        run.push(*value);
+        if run.len() >= best.len() {
+            best = run.clone();
+        }
+    }
+    best
+}
+
+// 理牌 依次取出王炸、炸弹、飞机和顺子 剩下的按张数排列
+fn arrange(cards: Vec<Card>) -> Vec<Card> {
+    let mut pool: BTreeMap<u8, Vec<Card>> = BTreeMap::new();
+    for card in cards {
+        pool.entry(card.rank.value()).or_default().push(card);
+    }
+    let mut groups: Vec<Vec<Card>> = vec![];
+    if pool.contains_key(&17) && pool.contains_key(&16) {
+        groups.push([take(&mut pool, 17, 1), take(&mut pool, 16, 1)].concat());
+    }
+    let bombs: Vec<u8> = pool
+        .iter()
+        .rev()
+        .filter(|(_, cards)| cards.len() >= 4)
+        .map(|(value, _)| *value)
+        .collect();
+    for value in bombs {
+        groups.push(take(&mut pool, value, 4));
+    }
+    // 飞机至少两个连续的三张 顺子至少5张
+    for (min, len) in [(3, 2), (1, 5)] {
+        loop {
+            let run = longest_run(&pool, min);
+            if run.len() < len {
+                break;
+            }
+            let group = run
+                .iter()
+                .rev()
+                .flat_map(|value| take(&mut pool, *value, min))
+                .collect();
+            groups.push(group);
+        }
+    }
+    let mut rest: Vec<Vec<Card>> = pool.into_values().collect();
+    rest.sort_by_key(|cards| Reverse((cards.len(), cards[0].rank.value())));
+    groups.extend(rest);
+    groups.concat()
+}
+
+// 牌局中自己的手牌
+fn local_cards(room: &Room, local: &Player, sort: HandSort) -> Vec<Card> {
+    let cards = room
+        .seat_of(local.id)
+        .and_then(|seat| room.game.as_ref().map(|game| game.hands[seat].clone()))
+        .unwrap_or_default();
+    sort_hand(&cards, sort)
+}
+
+// 手牌或排列方式变化时重建精灵 选中状态随之清空
+fn refresh_hand(
+    mut commands: Commands,
+    card_atlas: Res<CardAtlas>,
+    room: Res<Room>,
+    local: Res<Player>,
+    sort: Res<HandSort>,
+    mut hand: ResMut<LocalHand>,
+    mut drag: ResMut<HandDrag>,
+    query: Query<Entity, With<HandCard>>,
+) {
+    if !room.is_changed() && !sort.is_changed() {
+        return;
+    }
+    let cards = local_cards(&room, &local, *sort);
+    if cards == hand.0 {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (index, card) in cards.iter().enumerate() {
+        commands.spawn((
+            SpriteSheetBundle {
+                texture_atlas: card_atlas.atlas.clone(),
+                sprite: TextureAtlasSprite::new(get_sprite_index(card, &card_atlas)),
+                transform: Transform::from_scale(Vec3::splat(CARD_SCALE)),
+                ..Default::default()
+            },
+            *card,
+            HandCard {
+                index,
+                selected: false,
+            },
+            RoomUIComponent,
+        ));
+    }
+    hand.0 = cards;
+    drag.start = None;
+}
+
+// 手牌左边缘和间距 牌多时压缩间距保证不超出手牌区域
+fn hand_metrics(count: usize) -> (f32, f32) {
+    let spacing = if count > 1 {
+        CARD_SPACING.min((HAND_MAX_WIDTH - CARD_SIZE.x) / (count - 1) as f32)
+    } else {
+        0.
+    };
+    let width = CARD_SIZE.x + spacing * count.saturating_sub(1) as f32;
+    (-width / 2., spacing)
+}
+
+// 手牌所在的纵坐标
+fn hand_y(window: &Window) -> f32 {
+    -window.height() / 2. + HAND_BOTTOM_MARGIN + CARD_SIZE.y / 2.
+}
+
+// 鼠标下的牌 后面的牌压在前面的牌上 只露出左侧一条
+fn card_at(cursor: Vec2, count: usize, y: f32, cards: &[(usize, bool)]) -> Option<usize> {
+    let (left, spacing) = hand_metrics(count);
+    cards
+        .iter()
+        .filter(|(index, selected)| {
+            let x = left + spacing * *index as f32;
+            let bottom = y - CARD_SIZE.y / 2. + if *selected { SELECTED_OFFSET } else { 0. };
+            (x..=x + CARD_SIZE.x).contains(&cursor.x)
+                && (bottom..=bottom + CARD_SIZE.y).contains(&cursor.y)
+        })
+        .map(|(index, _)| *index)
+        .max()
+}
+
+// 点击选牌 拖动反选一段 右键或双击清空
+fn select_cards(
+    time: Res<Time>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    hand: Res<LocalHand>,
+    mut drag: ResMut<HandDrag>,
+    mut query: Query<&mut HandCard>,
+) {
+    if mouse.just_pressed(MouseButton::Right) {
+        clear_selection(&mut query, &mut drag);
+        return;
+    }
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), cameras.get_single())
+    else {
+        return;
+    };
+    let cards: Vec<(usize, bool)> = query.iter().map(|c| (c.index, c.selected)).collect();
+    let cursor = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor));
+    let hovered = cursor.and_then(|cursor| card_at(cursor, hand.0.len(), hand_y(window), &cards));
+    if mouse.just_pressed(MouseButton::Left) {
+        let now = time.elapsed_seconds();
+        let click = cursor.map(|cursor| (now, cursor));
+        let double = is_double_click(drag.last_click, click);
+        drag.last_click = click;
+        if double {
+            clear_selection(&mut query, &mut drag);
+            drag.last_click = None;
+            return;
+        }
+        if let Some(index) = hovered {
+            drag.start = Some(index);
+            drag.current = index;
+        }
+    }
+    if drag.start.is_none() {
+        return;
+    }
+    if let Some(index) = hovered {
+        drag.current = index;
+    }
+    if mouse.just_released(MouseButton::Left) {
+        if let Some(range) = drag.range() {
+            for mut card in query.iter_mut() {
+                if range.contains(&card.index) {
+                    card.selected = !card.selected;
+                }
+            }
+        }
+        drag.start = None;
+    }
+}
+
+// 两次点击间隔很短且位置几乎相同时为双击
+fn is_double_click(last: Option<(f32, Vec2)>, click: Option<(f32, Vec2)>) -> bool {
+    let (Some((last_time, last_pos)), Some((time, pos))) = (last, click) else {
+        return false;
+    };
+    time - last_time < DOUBLE_CLICK_SECS && last_pos.distance(pos) <= DOUBLE_CLICK_DISTANCE
+}
+
+fn clear_selection(query: &mut Query<&mut HandCard>, drag: &mut HandDrag) {
+    for mut card in query.iter_mut() {
+        if card.selected {
+            card.selected = false;
+        }
+    }
+    drag.start = None;
+}
+
+// 按位置排列手牌 选中的抬起 拖动经过的变暗
+fn layout_hand(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    hand: Res<LocalHand>,
+    drag: Res<HandDrag>,
+    mut query: Query<(&HandCard, &mut Transform, &mut TextureAtlasSprite)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let (left, spacing) = hand_metrics(hand.0.len());
+    let y = hand_y(window);
+    let range = drag.range();
+    for (card, mut transform, mut sprite) in query.iter_mut() {
+        transform.translation = Vec3::new(
+            left + spacing * card.index as f32 + CARD_SIZE.x / 2.,
+            y + if card.selected { SELECTED_OFFSET } else { 0. },
+            1. + card.index as f32 * 0.01,
+        );
+        sprite.color = if range.as_ref().is_some_and(|r| r.contains(&card.index)) {
+            Color::GRAY
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern::tests::cards;
+
+    fn values(cards: &[Card]) -> Vec<u8> {
+        cards.iter().map(|card| card.rank.value()).collect()
+    }
+
+    #[test]
+    fn rank_puts_two_and_jokers_first() {
+        let hand = sort_hand(&cards("3A2K01"), HandSort::Rank);
+        assert_eq!(values(&hand), [17, 16, 15, 14, 13, 3]);
+    }
+
+    #[test]
+    fn count_puts_bombs_and_triples_first() {
+        let hand = sort_hand(&cards("3KK5555Q2QQ"), HandSort::Count);
+        assert_eq!(values(&hand), [5, 5, 5, 5, 12, 12, 12, 13, 13, 15, 3]);
+    }
+
+    #[test]
+    fn arrange_groups_chains() {
+        let hand = sort_hand(&cards("0133344456789JJ2"), HandSort::Arrange);
+        assert_eq!(
+            values(&hand),
+            [17, 16, 4, 4, 4, 3, 3, 3, 9, 8, 7, 6, 5, 11, 11, 15]
+        );
+    }
+
+    #[test]
+    fn double_click_needs_the_same_spot() {
+        let first = Some((1., Vec2::new(100., -200.)));
+        assert!(is_double_click(first, Some((1.2, Vec2::new(102., -200.)))));
+        // 太慢或者点到另一张牌不算双击
+        assert!(!is_double_click(first, Some((1.5, Vec2::new(100., -200.)))));
+        assert!(!is_double_click(first, Some((1.1, Vec2::new(140., -200.)))));
+        assert!(!is_double_click(None, first));
+    }
+}
@@ -1,10 +1,17 @@
-use bevy::prelude::*;
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
 use bevy_matchbox::matchbox_socket::{PeerId, PeerState};
+use rand::Rng;
 
 use crate::{
-    common::{despawn_screen, AddressedEvent, AppState, Event, MyAssets, Socket},
-    player::Player,
-    room::{Room, RoomPlayer},
+    atlas::PlistAtlas,
+    common::{despawn_screen, AddressedEvent, AppMode, AppState, Event, MyAssets, Socket},
+    player::{Player, NICKNAME_MAX_CHARS},
+    replay::{ReplayStore, ReplayViewer},
+    room::{JoinRoomError, Room, RoomRules, RoomVisibility, Stake, ROOM_CODE_LEN},
+    stats::{StatsServer, StatsStore},
 };
 
 #[derive(Component)]
@@ -14,8 +21,150 @@ pub struct LobbyComponent;
 pub enum LobbyButton {
     EnterRoom,
     CreateRoom,
+    // 按选择的可见性创建房间
+    CreateRoomWith(RoomVisibility),
+    ShowRoomList,
+    CloseDialog,
+    // 加入对应房间号的房间
+    JoinRoom(u32),
+    // 数字键盘
+    KeypadDigit(u32),
+    KeypadClear,
+    KeypadBack,
+    // 切换头像
+    ToggleAvatar,
+    // 创建房间时选择场次
+    SelectStake(Stake),
+    // 开始或结束修改昵称
+    EditNickname,
+    // 切换语音包
+    ToggleVoice,
+    // 查看战绩和排行榜
+    ShowStats,
+    // 查看保存的回放
+    ShowReplays,
+    // 回放第几局
+    WatchReplay(usize),
+}
+
+// 大厅弹窗 同一时间只显示一个
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyDialog {
+    RoomList,
+    Keypad,
+    CreateRoom,
+    Stats,
+    Replays,
+}
+
+// 房间号输入框的第几位
+#[derive(Component)]
+pub struct RoomCodeDigit(usize);
+
+// 大厅提示文字
+#[derive(Component)]
+pub struct LobbyTipText;
+
+// 战绩弹窗中自己的战绩
+#[derive(Component)]
+pub struct StatsText;
+
+// 排行榜 列表项随战绩变化重建
+#[derive(Component)]
+pub struct Leaderboard;
+
+// 回放列表 列表项随保存的回放变化重建
+#[derive(Component)]
+pub struct ReplayList;
+
+// 自己的头像 点击切换男女
+#[derive(Component)]
+pub struct AvatarButton;
+
+// 个人资料中的昵称、语音包和金币
+#[derive(Component)]
+pub struct NicknameText;
+
+#[derive(Component)]
+pub struct VoiceText;
+
+#[derive(Component)]
+pub struct CoinsText;
+
+// 创建房间弹窗中的场次卡片 选中的高亮
+#[derive(Component)]
+pub struct StakeCard(Stake);
+
+// 创建房间时选中的场次
+#[derive(Resource, Default)]
+pub struct SelectedStake(Stake);
+
+// 正在修改的昵称 按回车或再次点击昵称保存 按Esc放弃
+#[derive(Resource, Default)]
+pub struct NicknameEditor {
+    editing: bool,
+    text: String,
+}
+
+// 数字键盘的用途
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeypadPurpose {
+    #[default]
+    RoomCode,
+    // 创建密码房时设置密码
+    CreatePassword,
+    // 加入对应房间号的密码房时输入密码
+    JoinPassword(u32),
+}
+
+// 数字键盘输入中的房间号或密码
+#[derive(Resource, Default)]
+pub struct RoomCodeInput {
+    digits: Vec<u32>,
+    purpose: KeypadPurpose,
+}
+
+impl RoomCodeInput {
+    fn code(&self) -> u32 {
+        self.digits.iter().fold(0, |code, digit| code * 10 + digit)
+    }
+
+    fn password(&self) -> String {
+        self.digits.iter().map(|digit| digit.to_string()).collect()
+    }
+
+    fn open(&mut self, purpose: KeypadPurpose) {
+        self.digits.clear();
+        self.purpose = purpose;
+    }
 }
 
+// 大厅提示信息 显示几秒后自动消失
+#[derive(Resource, Default)]
+pub struct LobbyTip {
+    message: String,
+    timer: Timer,
+}
+
+impl LobbyTip {
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.message = message.into();
+        info!("{}", self.message);
+        self.timer = Timer::from_seconds(LOBBY_TIP_SECONDS, TimerMode::Once);
+    }
+}
+
+// 房间列表 列表项随大厅房间变化重建
+#[derive(Component, Default)]
+pub struct RoomList {
+    position: f32,
+}
+
+const ROOM_ROW_HEIGHT: f32 = 56.;
+const ROOM_LIST_TEXT_COLOR: Color = Color::WHITE;
+const ROOM_LIST_DISABLED_COLOR: Color = Color::GRAY;
+const LOBBY_TIP_SECONDS: f32 = 3.;
+
 #[derive(Resource)]
 pub struct Lobby {
     rooms: Vec<Room>,
@@ -31,27 +180,95 @@ impl Lobby {
         self.rooms.push(room);
     }
 
-    // 删除大厅用户
-    fn remove_room_by_peer(&mut self, peer: PeerId) {
-        self.rooms.retain(|room| {
-            if room.owner.player.id == peer && room.players.len() <= 1 {
-                false
-            } else {
-                true
+    // 同步房间信息 已有的房间直接覆盖
+    pub fn sync_room(&mut self, room: Room) {
+        if let Some(exist) = self.rooms.iter_mut().find(|r| **r == room) {
+            *exist = room;
+        } else {
+            self.add_room(room);
+        }
+    }
+
+    // 生成一个未被占用的房间号
+    fn new_room_code(&self) -> u32 {
+        let min = 10u32.pow(ROOM_CODE_LEN as u32 - 1);
+        let mut rng = rand::thread_rng();
+        loop {
+            let code = rng.gen_range(min..min * 10);
+            if self.rooms.iter().all(|room| room.code != code) {
+                return code;
             }
-        })
+        }
+    }
+
+    // 检查房间号能否加入 密码由房主校验
+    fn check_join(
+        &self,
+        code: u32,
+        password: Option<&str>,
+        player: &Player,
+    ) -> Result<&Room, JoinRoomError> {
+        let room = self
+            .rooms
+            .iter()
+            .find(|room| room.code == code)
+            .ok_or(JoinRoomError::NotFound)?;
+        room.check_join(player)?;
+        if room.visibility == RoomVisibility::Private && password.is_none() {
+            return Err(JoinRoomError::PasswordRequired);
+        }
+        Ok(room)
+    }
+
+    pub fn remove_room(&mut self, code: u32) {
+        self.rooms.retain(|room| room.code != code)
+    }
+
+    // 房主断开连接 移除其房间 若还有其他玩家 新房主会重新同步房间信息
+    pub fn remove_room_by_peer(&mut self, peer: PeerId) {
+        self.rooms.retain(|room| !room.is_owner(peer))
     }
 }
 
 impl Plugin for LobbyComponent {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::Lobby), setup)
+        app.init_resource::<RoomCodeInput>()
+            .init_resource::<LobbyTip>()
+            .init_resource::<NicknameEditor>()
+            .init_resource::<SelectedStake>()
+            .add_systems(
+                OnEnter(AppState::Lobby),
+                setup.run_if(resource_equals(AppMode::Windowed)),
+            )
             .add_systems(Update, update.run_if(in_state(AppState::Lobby)))
+            .add_systems(
+                Update,
+                edit_nickname
+                    .run_if(in_state(AppState::Lobby))
+                    .run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                Update,
+                (
+                    refresh_room_list,
+                    scroll_room_list,
+                    refresh_room_code_input,
+                    refresh_avatar,
+                    refresh_profile,
+                    refresh_stake_cards,
+                    refresh_stats,
+                    refresh_replay_list,
+                    update_lobby_tip,
+                )
+                    .run_if(in_state(AppState::Lobby))
+                    .run_if(resource_equals(AppMode::Windowed)),
+            )
             .add_systems(OnExit(AppState::Lobby), (despawn_screen::<LobbyComponent>,));
     }
 }
 
-pub fn setup(mut commands: Commands, asset: Res<MyAssets>) {
+pub fn setup(mut commands: Commands, asset: Res<MyAssets>, plists: Res<Assets<PlistAtlas>>) {
+    let youqing_tip = plists.get(&asset.youqing_tip).expect("图集已加载");
     commands
         .spawn((
             NodeBundle {
@@ -76,23 +293,22 @@ pub fn setup(mut commands: Commands, asset: Res<MyAssets>) {
                 },
                 ..default()
             });
-            parent.spawn(ImageBundle {
-                image: asset.you_qing_girl.clone().into(),
-                style: Style {
+            youqing_tip.spawn_image(
+                parent,
+                "xiongmao1.png",
+                Style {
                     width: Val::Percent(30.),
                     height: Val::Percent(50.),
-                    // width: Val::P(390.),
-                    // height: Val::Px(370.),
                     position_type: PositionType::Absolute,
                     top: Val::Percent(14.),
                     left: Val::Percent(25.),
                     ..default()
                 },
-                ..Default::default()
-            });
-            parent.spawn(ImageBundle {
-                image: asset.you_qing_boy.clone().into(),
-                style: Style {
+            );
+            youqing_tip.spawn_image(
+                parent,
+                "xiongmao2.png",
+                Style {
                     width: Val::Percent(30.),
                     height: Val::Percent(50.),
                     position_type: PositionType::Absolute,
@@ -100,8 +316,130 @@ pub fn setup(mut commands: Commands, asset: Res<MyAssets>) {
                     left: Val::Percent(65.),
                     ..default()
                 },
-                ..Default::default()
-            });
+            );
+            parent.spawn((
+                ButtonBundle {
+                    image: asset.room_touxiang.clone().into(),
+                    style: Style {
+                        width: Val::Px(80.),
+                        height: Val::Px(80.),
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(2.),
+                        left: Val::Percent(2.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                LobbyButton::ToggleAvatar,
+                AvatarButton,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(2.),
+                        left: Val::Percent(10.),
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(6.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    let text_style = TextStyle {
+                        font: asset.font.clone(),
+                        font_size: 22.,
+                        color: Color::WHITE,
+                    };
+                    parent
+                        .spawn((ButtonBundle::default(), LobbyButton::EditNickname))
+                        .with_children(|parent| {
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font_size: 28.,
+                                        ..text_style.clone()
+                                    },
+                                ),
+                                NicknameText,
+                            ));
+                        });
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                column_gap: Val::Px(16.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                                            ..Default::default()
+                                        },
+                                        background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                                        ..Default::default()
+                                    },
+                                    LobbyButton::ToggleVoice,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        TextBundle::from_section("", text_style.clone()),
+                                        VoiceText,
+                                    ));
+                                });
+                            parent.spawn((
+                                TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        color: Color::GOLD,
+                                        ..text_style.clone()
+                                    },
+                                ),
+                                CoinsText,
+                            ));
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                                            ..Default::default()
+                                        },
+                                        background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                                        ..Default::default()
+                                    },
+                                    LobbyButton::ShowStats,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        "战绩",
+                                        text_style.clone(),
+                                    ));
+                                });
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                                            ..Default::default()
+                                        },
+                                        background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                                        ..Default::default()
+                                    },
+                                    LobbyButton::ShowReplays,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        "回放",
+                                        text_style.clone(),
+                                    ));
+                                });
+                        });
+                });
             parent
                 .spawn(ButtonBundle {
                     image: asset.btn_enter_room.clone().into(),
@@ -130,87 +468,1234 @@ pub fn setup(mut commands: Commands, asset: Res<MyAssets>) {
                     ..Default::default()
                 })
                 .insert(LobbyButton::CreateRoom);
-            parent.spawn(ImageBundle {
-                image: asset.tip.clone().into(),
-                style: Style {
+            youqing_tip.spawn_image(
+                parent,
+                "xiongmao3.png",
+                Style {
+                    width: Val::Px(226.),
+                    height: Val::Px(380.),
                     position_type: PositionType::Absolute,
                     top: Val::Percent(23.),
                     left: Val::Percent(3.),
                     ..Default::default()
                 },
-                ..Default::default()
-            });
+            );
+            // 房间列表弹窗 点击进入房间时显示
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(70.),
+                            height: Val::Percent(70.),
+                            position_type: PositionType::Absolute,
+                            top: Val::Percent(15.),
+                            left: Val::Percent(15.),
+                            flex_direction: FlexDirection::Column,
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..Default::default()
+                        },
+                        background_color: Color::rgba(0., 0., 0., 0.85).into(),
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(10),
+                        ..Default::default()
+                    },
+                    LobbyDialog::RoomList,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                height: Val::Px(40.),
+                                flex_direction: FlexDirection::Row,
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::SpaceBetween,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "房间列表",
+                                TextStyle {
+                                    font: asset.font.clone(),
+                                    font_size: 32.,
+                                    color: Color::GOLD,
+                                },
+                            ));
+                            parent
+                                .spawn(ButtonBundle {
+                                    image: asset.btn_close.clone().into(),
+                                    style: Style {
+                                        width: Val::Px(40.),
+                                        height: Val::Px(40.),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(LobbyButton::CloseDialog);
+                        });
+                    // 表头
+                    parent.spawn(room_row_node()).with_children(|parent| {
+                        for (label, width) in ROOM_LIST_COLUMNS {
+                            parent.spawn(room_row_text(
+                                label,
+                                width,
+                                Color::GOLD,
+                                asset.font.clone(),
+                            ));
+                        }
+                    });
+                    // 可滚动区域
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                flex_grow: 1.,
+                                flex_direction: FlexDirection::Column,
+                                overflow: Overflow::clip_y(),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn((
+                                NodeBundle {
+                                    style: Style {
+                                        width: Val::Percent(100.),
+                                        flex_direction: FlexDirection::Column,
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                },
+                                RoomList::default(),
+                            ));
+                        });
+                });
+            // 战绩弹窗 自己的战绩和排行榜
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(70.),
+                            height: Val::Percent(70.),
+                            position_type: PositionType::Absolute,
+                            top: Val::Percent(15.),
+                            left: Val::Percent(15.),
+                            flex_direction: FlexDirection::Column,
+                            padding: UiRect::all(Val::Px(16.)),
+                            row_gap: Val::Px(8.),
+                            ..Default::default()
+                        },
+                        background_color: Color::rgba(0., 0., 0., 0.85).into(),
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(10),
+                        ..Default::default()
+                    },
+                    LobbyDialog::Stats,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                height: Val::Px(40.),
+                                flex_direction: FlexDirection::Row,
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::SpaceBetween,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "我的战绩",
+                                TextStyle {
+                                    font: asset.font.clone(),
+                                    font_size: 32.,
+                                    color: Color::GOLD,
+                                },
+                            ));
+                            parent
+                                .spawn(ButtonBundle {
+                                    image: asset.btn_close.clone().into(),
+                                    style: Style {
+                                        width: Val::Px(40.),
+                                        height: Val::Px(40.),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(LobbyButton::CloseDialog);
+                        });
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: asset.font.clone(),
+                                font_size: 24.,
+                                color: ROOM_LIST_TEXT_COLOR,
+                            },
+                        ),
+                        StatsText,
+                    ));
+                    parent.spawn(TextBundle::from_section(
+                        "排行榜",
+                        TextStyle {
+                            font: asset.font.clone(),
+                            font_size: 32.,
+                            color: Color::GOLD,
+                        },
+                    ));
+                    parent.spawn(room_row_node()).with_children(|parent| {
+                        for (label, width) in LEADERBOARD_COLUMNS {
+                            parent.spawn(room_row_text(
+                                label,
+                                width,
+                                Color::GOLD,
+                                asset.font.clone(),
+                            ));
+                        }
+                    });
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                flex_direction: FlexDirection::Column,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        Leaderboard,
+                    ));
+                });
+            // 回放弹窗 最近的牌局在上面
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(70.),
+                            height: Val::Percent(70.),
+                            position_type: PositionType::Absolute,
+                            top: Val::Percent(15.),
+                            left: Val::Percent(15.),
+                            flex_direction: FlexDirection::Column,
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..Default::default()
+                        },
+                        background_color: Color::rgba(0., 0., 0., 0.85).into(),
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(10),
+                        ..Default::default()
+                    },
+                    LobbyDialog::Replays,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                height: Val::Px(40.),
+                                flex_direction: FlexDirection::Row,
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::SpaceBetween,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "牌局回放",
+                                TextStyle {
+                                    font: asset.font.clone(),
+                                    font_size: 32.,
+                                    color: Color::GOLD,
+                                },
+                            ));
+                            parent
+                                .spawn(ButtonBundle {
+                                    image: asset.btn_close.clone().into(),
+                                    style: Style {
+                                        width: Val::Px(40.),
+                                        height: Val::Px(40.),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(LobbyButton::CloseDialog);
+                        });
+                    parent.spawn(room_row_node()).with_children(|parent| {
+                        for (label, width) in REPLAY_LIST_COLUMNS {
+                            parent.spawn(room_row_text(
+                                label,
+                                width,
+                                Color::GOLD,
+                                asset.font.clone(),
+                            ));
+                        }
+                    });
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.),
+                                flex_grow: 1.,
+                                flex_direction: FlexDirection::Column,
+                                overflow: Overflow::clip_y(),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        },
+                        ReplayList,
+                    ));
+                });
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Percent(16.),
+                        height: Val::Percent(7.),
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(84.),
+                        left: Val::Percent(42.),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    background_color: Color::ORANGE.into(),
+                    ..Default::default()
+                })
+                .insert(LobbyButton::ShowRoomList)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "房间列表",
+                        TextStyle {
+                            font: asset.font.clone(),
+                            font_size: 28.,
+                            color: ROOM_LIST_TEXT_COLOR,
+                        },
+                    ));
+                });
+            // 输入房间号的数字键盘
+            parent
+                .spawn((
+                    ImageBundle {
+                        image: asset.join_input_bg.clone().into(),
+                        style: Style {
+                            width: Val::Percent(48.),
+                            height: Val::Percent(72.),
+                            position_type: PositionType::Absolute,
+                            top: Val::Percent(14.),
+                            left: Val::Percent(26.),
+                            ..Default::default()
+                        },
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(10),
+                        ..Default::default()
+                    },
+                    LobbyDialog::Keypad,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(ButtonBundle {
+                            image: asset.btn_close.clone().into(),
+                            style: Style {
+                                width: Val::Px(48.),
+                                height: Val::Px(48.),
+                                position_type: PositionType::Absolute,
+                                top: Val::Percent(10.),
+                                right: Val::Px(-12.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(LobbyButton::CloseDialog);
+                    // 已输入的房间号
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(74.),
+                                height: Val::Percent(12.),
+                                position_type: PositionType::Absolute,
+                                top: Val::Percent(17.5),
+                                left: Val::Percent(12.5),
+                                flex_direction: FlexDirection::Row,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            for index in 0..ROOM_CODE_LEN {
+                                parent.spawn((
+                                    TextBundle::from_section(
+                                        "",
+                                        TextStyle {
+                                            font: asset.font.clone(),
+                                            font_size: 40.,
+                                            color: Color::MAROON,
+                                        },
+                                    )
+                                    .with_text_alignment(TextAlignment::Center)
+                                    .with_style(Style {
+                                        width: Val::Percent(100. / ROOM_CODE_LEN as f32),
+                                        justify_content: JustifyContent::Center,
+                                        ..Default::default()
+                                    }),
+                                    RoomCodeDigit(index),
+                                ));
+                            }
+                        });
+                    // 1-9 清除 0 后退
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Percent(84.),
+                                height: Val::Percent(60.),
+                                position_type: PositionType::Absolute,
+                                top: Val::Percent(34.),
+                                left: Val::Percent(8.),
+                                flex_direction: FlexDirection::Row,
+                                flex_wrap: FlexWrap::Wrap,
+                                justify_content: JustifyContent::SpaceEvenly,
+                                align_content: AlignContent::SpaceEvenly,
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            let keys = (1..10)
+                                .map(|digit| {
+                                    (
+                                        asset.join_digits[digit].clone(),
+                                        LobbyButton::KeypadDigit(digit as u32),
+                                    )
+                                })
+                                .chain([
+                                    (asset.join_clear.clone(), LobbyButton::KeypadClear),
+                                    (asset.join_digits[0].clone(), LobbyButton::KeypadDigit(0)),
+                                    (asset.join_back.clone(), LobbyButton::KeypadBack),
+                                ]);
+                            for (image, button) in keys {
+                                parent
+                                    .spawn(ButtonBundle {
+                                        image: image.into(),
+                                        style: Style {
+                                            width: Val::Percent(30.),
+                                            height: Val::Percent(22.),
+                                            ..Default::default()
+                                        },
+                                        ..Default::default()
+                                    })
+                                    .insert(button);
+                            }
+                        });
+                });
+            // 创建房间 选择场次和房间可见性
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(60.),
+                            height: Val::Percent(80.),
+                            position_type: PositionType::Absolute,
+                            top: Val::Percent(10.),
+                            left: Val::Percent(20.),
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::SpaceEvenly,
+                            padding: UiRect::all(Val::Px(16.)),
+                            ..Default::default()
+                        },
+                        background_color: Color::rgba(0., 0., 0., 0.85).into(),
+                        visibility: Visibility::Hidden,
+                        z_index: ZIndex::Global(10),
+                        ..Default::default()
+                    },
+                    LobbyDialog::CreateRoom,
+                ))
+                .with_children(|parent| {
+                    parent
+                        .spawn(ButtonBundle {
+                            image: asset.btn_close.clone().into(),
+                            style: Style {
+                                width: Val::Px(40.),
+                                height: Val::Px(40.),
+                                position_type: PositionType::Absolute,
+                                top: Val::Px(8.),
+                                right: Val::Px(8.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(LobbyButton::CloseDialog);
+                    parent.spawn(TextBundle::from_section(
+                        "创建房间",
+                        TextStyle {
+                            font: asset.font.clone(),
+                            font_size: 32.,
+                            color: Color::GOLD,
+                        },
+                    ));
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                column_gap: Val::Px(12.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            for (stake, image) in Stake::ALL.iter().zip(&asset.btn_stakes) {
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            image: image.clone().into(),
+                                            style: Style {
+                                                width: Val::Px(110.),
+                                                height: Val::Px(145.),
+                                                justify_content: JustifyContent::Center,
+                                                ..Default::default()
+                                            },
+                                            ..Default::default()
+                                        },
+                                        LobbyButton::SelectStake(*stake),
+                                        StakeCard(*stake),
+                                    ))
+                                    .with_children(|parent| {
+                                        // 卡片底部的进度条上显示最低金币
+                                        parent.spawn(
+                                            TextBundle::from_section(
+                                                stake.min_coins().to_string(),
+                                                TextStyle {
+                                                    font: asset.font.clone(),
+                                                    font_size: 16.,
+                                                    color: Color::WHITE,
+                                                },
+                                            )
+                                            .with_style(Style {
+                                                position_type: PositionType::Absolute,
+                                                bottom: Val::Percent(10.),
+                                                ..Default::default()
+                                            }),
+                                        );
+                                    });
+                            }
+                        });
+                    for (visibility, description) in [
+                        (RoomVisibility::Public, "公开房间 所有人可见"),
+                        (RoomVisibility::Private, "密码房间 凭房间号和密码加入"),
+                        (RoomVisibility::InviteOnly, "邀请房间 凭房间号加入"),
+                    ] {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    width: Val::Percent(80.),
+                                    height: Val::Px(ROOM_ROW_HEIGHT),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: Color::ORANGE.into(),
+                                ..Default::default()
+                            })
+                            .insert(LobbyButton::CreateRoomWith(visibility))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    description,
+                                    TextStyle {
+                                        font: asset.font.clone(),
+                                        font_size: 24.,
+                                        color: ROOM_LIST_TEXT_COLOR,
+                                    },
+                                ));
+                            });
+                    }
+                });
+            // 提示显示在弹窗之上
+            parent.spawn((
+                TextBundle {
+                    z_index: ZIndex::Global(20),
+                    ..TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: asset.font.clone(),
+                            font_size: 32.,
+                            color: Color::ORANGE_RED,
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Percent(6.),
+                        ..Default::default()
+                    })
+                },
+                LobbyTipText,
+            ));
         });
 }
 
+// 显示指定的弹窗 其余弹窗隐藏
+fn show_dialog(dialogs: &mut Query<(&mut Visibility, &LobbyDialog)>, show: Option<LobbyDialog>) {
+    for (mut visibility, dialog) in dialogs.iter_mut() {
+        *visibility = if Some(*dialog) == show {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+// 按房间号请求加入房间 加入请求由房主处理
+fn request_join(
+    lobby: &Lobby,
+    code: u32,
+    password: Option<String>,
+    socket: &mut Socket,
+    player: &Player,
+) -> Result<(), JoinRoomError> {
+    let owner = lobby
+        .check_join(code, password.as_deref(), player)?
+        .owner
+        .player
+        .id;
+    info!("请求加入房间{}", code);
+    socket.send_reliable(
+        AddressedEvent {
+            src: player.clone(),
+            event: Event::JoinRoom { code, password },
+        },
+        vec![owner],
+    );
+    Ok(())
+}
+
+// 创建房间 通知其他客户端房间信息
+fn create_room(
+    commands: &mut Commands,
+    lobby: &mut Lobby,
+    player: &Player,
+    state: &mut NextState<AppState>,
+    stake: Stake,
+    visibility: RoomVisibility,
+    password: Option<String>,
+) -> Result<(), JoinRoomError> {
+    if !stake.affordable(player) {
+        return Err(JoinRoomError::NotEnoughCoins);
+    }
+    let mut room =
+        Room::new(player.clone(), lobby.new_room_code()).with_visibility(visibility, password);
+    room.rules = RoomRules {
+        stake,
+        ..room.rules
+    };
+    lobby.add_room(room.clone());
+    commands.insert_resource(room);
+    commands.insert_resource(player.to_owned());
+    state.set(AppState::InRoom);
+    Ok(())
+}
+
+// 选中的场次卡片正常显示 其他的变暗 金币不够的场次更暗
+fn refresh_stake_cards(
+    selected: Res<SelectedStake>,
+    player: Res<Player>,
+    mut query: Query<(&mut BackgroundColor, &StakeCard)>,
+) {
+    if !selected.is_changed() && !player.is_changed() {
+        return;
+    }
+    for (mut color, StakeCard(stake)) in query.iter_mut() {
+        *color = if *stake == selected.0 {
+            Color::WHITE
+        } else if stake.affordable(&player) {
+            Color::GRAY
+        } else {
+            Color::DARK_GRAY
+        }
+        .into();
+    }
+}
+
+// 刷新输入框中的房间号
+pub fn refresh_room_code_input(
+    input: Res<RoomCodeInput>,
+    mut query: Query<(&mut Text, &RoomCodeDigit)>,
+) {
+    if !input.is_changed() {
+        return;
+    }
+    for (mut text, RoomCodeDigit(index)) in query.iter_mut() {
+        text.sections[0].value = input
+            .digits
+            .get(*index)
+            .map(|digit| digit.to_string())
+            .unwrap_or_default();
+    }
+}
+
+// 头像和性别标志 切换后重建
+fn refresh_avatar(
+    mut commands: Commands,
+    asset: Res<MyAssets>,
+    plists: Res<Assets<PlistAtlas>>,
+    player: Res<Player>,
+    query: Query<Entity, With<AvatarButton>>,
+    added: Query<(), Added<AvatarButton>>,
+) {
+    if !player.is_changed() && added.is_empty() {
+        return;
+    }
+    let Some(public_ui) = plists.get(&asset.public_ui) else {
+        return;
+    };
+    for entity in query.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            public_ui.spawn_image(
+                parent,
+                player.profile.avatar.head_frame(),
+                Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(4.),
+                    left: Val::Px(4.),
+                    width: Val::Px(72.),
+                    height: Val::Px(72.),
+                    ..Default::default()
+                },
+            );
+            public_ui.spawn_image(
+                parent,
+                player.profile.avatar.sex_frame(),
+                Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(-4.),
+                    right: Val::Px(-4.),
+                    width: Val::Px(28.),
+                    height: Val::Px(28.),
+                    ..Default::default()
+                },
+            );
+        });
+    }
+}
+
+// 昵称、语音包和金币 修改昵称时显示输入中的文字和光标
+fn refresh_profile(
+    player: Res<Player>,
+    editor: Res<NicknameEditor>,
+    mut nickname: Query<&mut Text, (With<NicknameText>, Without<VoiceText>, Without<CoinsText>)>,
+    mut voice: Query<&mut Text, (With<VoiceText>, Without<CoinsText>)>,
+    mut coins: Query<&mut Text, With<CoinsText>>,
+    added: Query<(), Added<NicknameText>>,
+) {
+    if !player.is_changed() && !editor.is_changed() && added.is_empty() {
+        return;
+    }
+    for mut text in nickname.iter_mut() {
+        text.sections[0].value = if editor.editing {
+            format!("{}|", editor.text)
+        } else {
+            player.name()
+        };
+    }
+    for mut text in voice.iter_mut() {
+        text.sections[0].value = format!("语音: {}", player.profile.voice.label());
+    }
+    for mut text in coins.iter_mut() {
+        text.sections[0].value = format!("金币: {}", player.profile.coins);
+    }
+}
+
+// 修改昵称时接收键盘输入
+fn edit_nickname(
+    mut chars: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<NicknameEditor>,
+    mut player: ResMut<Player>,
+) {
+    if !editor.editing {
+        chars.clear();
+        return;
+    }
+    for event in chars.read() {
+        if !event.char.is_control() && editor.text.chars().count() < NICKNAME_MAX_CHARS {
+            editor.text.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        editor.text.pop();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        editor.editing = false;
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        save_nickname(&mut editor, &mut player);
+    }
+}
+
+// 结束修改 昵称不能为空
+fn save_nickname(editor: &mut NicknameEditor, player: &mut Player) {
+    editor.editing = false;
+    let nickname = editor.text.trim();
+    if !nickname.is_empty() && nickname != player.profile.nickname {
+        player.profile.nickname = nickname.to_string();
+        player.profile.save();
+    }
+}
+
+pub fn update_lobby_tip(
+    time: Res<Time>,
+    mut tip: ResMut<LobbyTip>,
+    mut query: Query<&mut Text, With<LobbyTipText>>,
+) {
+    tip.timer.tick(time.delta());
+    let message = if tip.timer.finished() {
+        ""
+    } else {
+        tip.message.as_str()
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != message {
+            text.sections[0].value = message.to_string();
+        }
+    }
+}
+
+// 排行榜的列 标题和宽度百分比
+const LEADERBOARD_COLUMNS: [(&str, f32); 6] = [
+    ("排名", 10.),
+    ("玩家", 30.),
+    ("局数", 15.),
+    ("胜率", 15.),
+    ("连胜", 15.),
+    ("输赢金币", 15.),
+];
+
+// 回放列表的列 标题和宽度百分比
+const REPLAY_LIST_COLUMNS: [(&str, f32); 4] =
+    [("玩法", 35.), ("地主", 20.), ("结果", 15.), ("得分", 15.)];
+
+// 房间列表的列 标题和宽度百分比
+const ROOM_LIST_COLUMNS: [(&str, f32); 6] = [
+    ("房间号", 15.),
+    ("房主", 20.),
+    ("人数", 12.),
+    ("状态", 13.),
+    ("规则", 20.),
+    ("", 20.),
+];
+
+fn room_row_node() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Px(ROOM_ROW_HEIGHT),
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            flex_shrink: 0.,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn room_row_text(value: &str, width: f32, color: Color, font: Handle<Font>) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font,
+            font_size: 24.,
+            color,
+        },
+    )
+    .with_style(Style {
+        width: Val::Percent(width),
+        ..Default::default()
+    })
+}
+
+// 战绩变化时刷新自己的战绩和排行榜
+fn refresh_stats(
+    mut commands: Commands,
+    asset: Res<MyAssets>,
+    store: Res<StatsStore>,
+    player: Res<Player>,
+    mut text: Query<&mut Text, With<StatsText>>,
+    list: Query<Entity, With<Leaderboard>>,
+    added: Query<(), Added<Leaderboard>>,
+) {
+    if !store.is_changed() && added.is_empty() {
+        return;
+    }
+    let stats = store.get(player.profile.uid);
+    for mut text in text.iter_mut() {
+        text.sections[0].value = format!(
+            "总局数: {}  胜率: {}  地主胜率: {}({}局)  农民胜率: {}({}局)\n炸弹: {}  春天: {}  单局最多赢: {}  {}",
+            stats.games,
+            stats.win_rate(),
+            stats.landlord_win_rate(),
+            stats.landlord_games,
+            stats.farmer_win_rate(),
+            stats.farmer_games,
+            stats.bombs,
+            stats.springs,
+            stats.biggest_win,
+            stats.streak_label(),
+        );
+    }
+    for entity in list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for (rank, (uid, stats)) in store.leaderboard().iter().enumerate() {
+                // 自己高亮显示
+                let color = if *uid == player.profile.uid {
+                    Color::GOLD
+                } else {
+                    ROOM_LIST_TEXT_COLOR
+                };
+                let columns = [
+                    (rank + 1).to_string(),
+                    stats.nickname.clone(),
+                    stats.games.to_string(),
+                    stats.win_rate(),
+                    stats.streak_label(),
+                    format!("{:+}", stats.total_coins),
+                ];
+                parent.spawn(room_row_node()).with_children(|parent| {
+                    for (value, (_, width)) in columns.iter().zip(LEADERBOARD_COLUMNS) {
+                        parent.spawn(room_row_text(value, width, color, asset.font.clone()));
+                    }
+                });
+            }
+        });
+    }
+}
+
+// 保存的回放变化时重建回放列表
+fn refresh_replay_list(
+    mut commands: Commands,
+    asset: Res<MyAssets>,
+    store: Res<ReplayStore>,
+    list: Query<Entity, With<ReplayList>>,
+    added: Query<(), Added<ReplayList>>,
+) {
+    if !store.is_changed() && added.is_empty() {
+        return;
+    }
+    for entity in list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for (index, replay) in store.replays.iter().enumerate().rev() {
+                let result = &replay.result;
+                let columns = [
+                    replay.rules.summary(),
+                    replay.name(replay.landlord),
+                    if result.landlord_won {
+                        "地主胜"
+                    } else {
+                        "农民胜"
+                    }
+                    .to_string(),
+                    format!("{:+}", result.scores[replay.seat]),
+                ];
+                parent.spawn(room_row_node()).with_children(|parent| {
+                    for (value, (_, width)) in columns.iter().zip(REPLAY_LIST_COLUMNS) {
+                        parent.spawn(room_row_text(
+                            value,
+                            width,
+                            ROOM_LIST_TEXT_COLOR,
+                            asset.font.clone(),
+                        ));
+                    }
+                    parent
+                        .spawn(ButtonBundle {
+                            style: Style {
+                                width: Val::Percent(15.),
+                                height: Val::Px(ROOM_ROW_HEIGHT - 12.),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..Default::default()
+                            },
+                            background_color: Color::ORANGE.into(),
+                            ..Default::default()
+                        })
+                        .insert(LobbyButton::WatchReplay(index))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "回放",
+                                TextStyle {
+                                    font: asset.font.clone(),
+                                    font_size: 24.,
+                                    color: ROOM_LIST_TEXT_COLOR,
+                                },
+                            ));
+                        });
+                });
+            }
+        });
+    }
+}
+
+// 大厅房间变化时重建房间列表
+pub fn refresh_room_list(
+    mut commands: Commands,
+    asset: Res<MyAssets>,
+    lobby: Res<Lobby>,
+    player: Res<Player>,
+    list: Query<Entity, With<RoomList>>,
+    added: Query<(), Added<RoomList>>,
+) {
+    if !lobby.is_changed() && added.is_empty() {
+        return;
+    }
+    for entity in list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            // 密码房和邀请房不显示在列表中
+            for room in lobby
+                .rooms
+                .iter()
+                .filter(|room| room.visibility == RoomVisibility::Public)
+            {
+                // 满员或游戏中的房间置灰 不显示加入按钮
+                let joinable = room.check_join(&player).is_ok();
+                let color = if joinable {
+                    ROOM_LIST_TEXT_COLOR
+                } else {
+                    ROOM_LIST_DISABLED_COLOR
+                };
+                let columns = [
+                    room.code.to_string(),
+                    room.owner.player.name(),
+                    format!("{}/{}", room.seated(), room.players.len()),
+                    room.state.label().to_string(),
+                    room.rules.summary(),
+                ];
+                parent.spawn(room_row_node()).with_children(|parent| {
+                    for (value, (_, width)) in columns.iter().zip(ROOM_LIST_COLUMNS) {
+                        parent.spawn(room_row_text(value, width, color, asset.font.clone()));
+                    }
+                    if joinable {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    width: Val::Percent(15.),
+                                    height: Val::Px(ROOM_ROW_HEIGHT - 12.),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: Color::ORANGE.into(),
+                                ..Default::default()
+                            })
+                            .insert(LobbyButton::JoinRoom(room.code))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "加入",
+                                    TextStyle {
+                                        font: asset.font.clone(),
+                                        font_size: 24.,
+                                        color: ROOM_LIST_TEXT_COLOR,
+                                    },
+                                ));
+                            });
+                    }
+                });
+            }
+        });
+    }
+}
+
+// 鼠标滚轮滚动房间列表
+pub fn scroll_room_list(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut query_list: Query<(&mut RoomList, &mut Style, &Parent, &Node)>,
+    query_node: Query<&Node>,
+) {
+    for mouse_wheel_event in mouse_wheel_events.read() {
+        for (mut list, mut style, parent, list_node) in &mut query_list {
+            let items_height = list_node.size().y;
+            let Ok(container) = query_node.get(parent.get()) else {
+                continue;
+            };
+            let container_height = container.size().y;
+            let max_scroll = (items_height - container_height).max(0.);
+            let dy = match mouse_wheel_event.unit {
+                MouseScrollUnit::Line => mouse_wheel_event.y * ROOM_ROW_HEIGHT,
+                MouseScrollUnit::Pixel => mouse_wheel_event.y,
+            };
+            list.position = (list.position + dy).clamp(-max_scroll, 0.);
+            style.top = Val::Px(list.position);
+        }
+    }
+}
+
 pub fn update(
     query: Query<(&Interaction, &LobbyButton), (Changed<Interaction>, With<Button>)>,
+    mut dialogs: Query<(&mut Visibility, &LobbyDialog)>,
     mut commands: Commands,
+    mut input: ResMut<RoomCodeInput>,
+    mut tip: ResMut<LobbyTip>,
     mut socket: ResMut<Socket>,
     mut lobby: ResMut<Lobby>,
     mut state: ResMut<NextState<AppState>>,
-    player: ResMut<Player>,
+    mut player: ResMut<Player>,
+    mut nickname: ResMut<NicknameEditor>,
+    mut stake: ResMut<SelectedStake>,
+    mut stats: ResMut<StatsStore>,
+    replays: Res<ReplayStore>,
+    mut stats_server: ResMut<StatsServer>,
 ) {
     // 按钮事件
     for (interaction, button) in query.iter() {
         if *interaction == Interaction::Pressed {
             match button {
                 LobbyButton::EnterRoom => {
-                    // 加入房间 没有房间则创建 有则加入
-                    let rooms = lobby.rooms.to_owned();
-                    if let Some(room) = rooms
-                        .iter()
-                        .find(|r| r.players.iter().filter(|p| p.is_none()).count() > 0)
-                    {
-                        println!("请求加入房间{:?}", room);
-                        socket.send_unreliable(
+                    input.open(KeypadPurpose::RoomCode);
+                    show_dialog(&mut dialogs, Some(LobbyDialog::Keypad));
+                }
+                LobbyButton::ShowRoomList => show_dialog(&mut dialogs, Some(LobbyDialog::RoomList)),
+                LobbyButton::CloseDialog => show_dialog(&mut dialogs, None),
+                LobbyButton::JoinRoom(code) => {
+                    if let Err(err) = request_join(&lobby, *code, None, &mut socket, &player) {
+                        tip.show(err.to_string());
+                    }
+                }
+                LobbyButton::KeypadDigit(digit) => {
+                    if input.digits.len() < ROOM_CODE_LEN {
+                        input.digits.push(*digit);
+                    }
+                    // 输满后直接提交
+                    if input.digits.len() == ROOM_CODE_LEN {
+                        match input.purpose {
+                            KeypadPurpose::RoomCode => {
+                                let code = input.code();
+                                match request_join(&lobby, code, None, &mut socket, &player) {
+                                    // 密码房 继续输入密码
+                                    Err(JoinRoomError::PasswordRequired) => {
+                                        tip.show(JoinRoomError::PasswordRequired.to_string());
+                                        input.open(KeypadPurpose::JoinPassword(code));
+                                    }
+                                    Err(err) => {
+                                        tip.show(err.to_string());
+                                        input.open(KeypadPurpose::RoomCode);
+                                    }
+                                    Ok(()) => input.open(KeypadPurpose::RoomCode),
+                                }
+                            }
+                            KeypadPurpose::JoinPassword(code) => {
+                                let password = Some(input.password());
+                                if let Err(err) =
+                                    request_join(&lobby, code, password, &mut socket, &player)
+                                {
+                                    tip.show(err.to_string());
+                                }
+                                input.open(KeypadPurpose::RoomCode);
+                            }
+                            KeypadPurpose::CreatePassword => {
+                                let password = Some(input.password());
+                                if let Err(err) = create_room(
+                                    &mut commands,
+                                    &mut lobby,
+                                    &player,
+                                    &mut state,
+                                    stake.0,
+                                    RoomVisibility::Private,
+                                    password,
+                                ) {
+                                    tip.show(err.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                LobbyButton::ToggleAvatar => {
+                    player.profile.avatar = player.profile.avatar.toggled();
+                    player.profile.save();
+                }
+                LobbyButton::ToggleVoice => {
+                    player.profile.voice = player.profile.voice.toggled();
+                    player.profile.save();
+                }
+                LobbyButton::EditNickname if nickname.editing => {
+                    save_nickname(&mut nickname, &mut player)
+                }
+                LobbyButton::EditNickname => {
+                    nickname.editing = true;
+                    nickname.text = player.profile.nickname.clone();
+                }
+                LobbyButton::KeypadClear => input.digits.clear(),
+                LobbyButton::KeypadBack => {
+                    input.digits.pop();
+                }
+                LobbyButton::CreateRoom => show_dialog(&mut dialogs, Some(LobbyDialog::CreateRoom)),
+                LobbyButton::CreateRoomWith(RoomVisibility::Private) => {
+                    // 先设置密码再创建
+                    tip.show("请设置房间密码");
+                    input.open(KeypadPurpose::CreatePassword);
+                    show_dialog(&mut dialogs, Some(LobbyDialog::Keypad));
+                }
+                LobbyButton::CreateRoomWith(visibility) => {
+                    if let Err(err) = create_room(
+                        &mut commands,
+                        &mut lobby,
+                        &player,
+                        &mut state,
+                        stake.0,
+                        *visibility,
+                        None,
+                    ) {
+                        tip.show(err.to_string());
+                    }
+                }
+                LobbyButton::SelectStake(selected) => stake.0 = *selected,
+                LobbyButton::ShowReplays => show_dialog(&mut dialogs, Some(LobbyDialog::Replays)),
+                LobbyButton::WatchReplay(index) => {
+                    if let Some(replay) = replays.replays.get(*index) {
+                        commands.insert_resource(ReplayViewer::new(replay.clone()));
+                        state.set(AppState::Replay);
+                    }
+                }
+                LobbyButton::ShowStats => {
+                    show_dialog(&mut dialogs, Some(LobbyDialog::Stats));
+                    // 向排行榜服务器请求排行榜 没有服务器时只显示本地记录
+                    if let Some(server) = stats_server.peer(&socket) {
+                        socket.send_reliable(
                             AddressedEvent {
                                 src: player.clone(),
-                                event: Event::JoinRoom,
+                                event: Event::LeaderboardRequest,
                             },
-                            room.players
-                                .iter()
-                                .filter(|p| p.is_some())
-                                .map(|v| v.clone().unwrap().player.id)
-                                .collect::<Vec<PeerId>>(),
+                            vec![server],
                         );
-                    } else {
-                        // 创建房间 通知其他客户端房间信息
-                        let room = Room::new(player.clone());
-                        lobby.add_room(room.clone());
-                        commands.insert_resource(room.clone());
-                        state.set(AppState::InRoom);
                     }
                 }
-                LobbyButton::CreateRoom => {
-                    // 创建房间 通知其他客户端房间信息
-                    let room = Room::new(player.clone());
-                    lobby.add_room(room.clone());
-                    commands.insert_resource(room.clone());
-                    commands.insert_resource(player.to_owned());
-                    state.set(AppState::InRoom);
-                }
             }
         }
     }
-    // 删除断开链接的用户
-    for (peer, state) in socket.update_peers_unreliable() {
+    // 删除断开链接的用户 排行榜服务器向新连入的客户端发出上线消息
+    for (peer, state) in socket.peer_changes() {
         match state {
+            PeerState::Connected if *stats_server == StatsServer::Local => {
+                socket.send_reliable(
+                    AddressedEvent {
+                        src: player.clone(),
+                        event: Event::StatsServerOnline,
+                    },
+                    vec![peer],
+                );
+            }
             PeerState::Disconnected => lobby.remove_room_by_peer(peer),
             _ => {}
         }
     }
     // 同步房间信息
-    socket.receive_unreliable().iter().for_each(
-        move |AddressedEvent { src: _, event }| match event {
-            Event::SyncRoom(room) => {
-                if !lobby.rooms.contains(&room) {
-                    println!("add new room {:?}", room);
-                    lobby.add_room(room.to_owned());
-                }
-            }
-            Event::JoinRoom => todo!(),
-            Event::JoinRoomSuccess(room) => {
+    socket
+        .receive()
+        .iter()
+        .for_each(move |AddressedEvent { src, event }| match event {
+            Event::SyncRoom(room) => lobby.sync_room(room.to_owned()),
+            Event::JoinRoomRejected(err) => tip.show(err.to_string()),
+            Event::DissolveRoom(code) => lobby.remove_room(*code),
+            Event::JoinRoomSuccess(room) if src.id == room.owner.player.id => {
                 commands.insert_resource(room.to_owned());
                 commands.insert_resource(player.to_owned());
                 if room
@@ -228,7 +1713,24 @@ pub fn update(
                     state.set(AppState::InRoom);
                 }
             }
-            Event::Test(_) => todo!(),
-        },
-    );
+            // 排行榜服务器汇总各房间的结算 并回复排行榜
+            Event::Settlement(records) if *stats_server == StatsServer::Local => {
+                stats.record(records);
+                stats.save();
+            }
+            Event::StatsServerOnline => stats_server.announced(src),
+            Event::LeaderboardRequest if *stats_server == StatsServer::Local => {
+                socket.send_reliable(
+                    AddressedEvent {
+                        src: player.clone(),
+                        event: Event::Leaderboard(stats.leaderboard()),
+                    },
+                    vec![src.id],
+                );
+            }
+            Event::Leaderboard(leaderboard) if stats_server.is(src.id) => {
+                stats.merge(leaderboard, player.profile.uid)
+            }
+            _ => {}
+        });
 }
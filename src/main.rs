@@ -1,60 +1,114 @@
 // #![windows_subsystem = "windows"]
-use bevy::{
-    prelude::*,
-    window::{EnabledButtons, WindowMode, WindowResolution},
-};
-use bevy_ggrs::GgrsConfig;
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+use std::time::Duration;
 
+use bevy::{app::ScheduleRunnerPlugin, log::LogPlugin, prelude::*, window::EnabledButtons};
+
+mod atlas;
+mod audio;
 mod card;
 mod card_deck;
 mod common;
+mod counter;
+mod game;
+mod hand;
 mod lobby;
+mod pattern;
 mod player;
+mod replay;
 mod room;
 mod start_menu;
+mod stats;
+mod storage;
+mod table;
 
+use atlas::{PlistAtlas, PlistAtlasLoader};
+use audio::SoundPlugin;
 use bevy_asset_loader::prelude::*;
 use bevy_embedded_assets::EmbeddedAssetPlugin;
 use bevy_rapier2d::prelude::*;
+use counter::CardCounterPlugin;
+use hand::HandPlugin;
 use lobby::LobbyComponent;
+use replay::ReplayPlugin;
 use room::RoomUIComponent;
 use start_menu::StartMenuPlugin;
+use stats::{StatsPlugin, StatsServer};
+use table::TablePlugin;
 
-use common::{AppState, MyAssets};
+use common::{AppMode, AppState, MyAssets};
 
 const BACKGROUND_COLOR: Color = Color::BLACK;
+// 无窗口模式下的帧间隔
+const HEADLESS_FRAME_TIME: Duration = Duration::from_millis(1000 / 60);
 
 fn main() {
-    App::new()
-        .add_state::<AppState>()
-        .add_plugins((DefaultPlugins
-            .set(WindowPlugin {
-                primary_window: Some(Window {
-                    title: "斗地主".into(),
-                    fit_canvas_to_parent: true,
-                    resizable: false,
-                    enabled_buttons: EnabledButtons {
-                        maximize: false,
+    // `--headless` 以无窗口模式启动 用于机器人对局、专用服务器和CI
+    // `--stats-server` 作为排行榜服务器启动 客户端按固定的玩家编号认出服务器
+    let mode = if std::env::args().any(|arg| arg == "--headless") {
+        AppMode::Headless
+    } else {
+        AppMode::Windowed
+    };
+    build_app(mode)
+        .insert_resource(StatsServer::from_args(std::env::args()))
+        .run();
+}
+
+// 构建游戏 状态机与各个游戏插件在两种模式下保持一致
+pub fn build_app(mode: AppMode) -> App {
+    let mut app = App::new();
+    app.insert_resource(mode).add_state::<AppState>();
+    match mode {
+        AppMode::Windowed => {
+            app.add_plugins((DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "斗地主".into(),
+                        fit_canvas_to_parent: true,
+                        resizable: false,
+                        enabled_buttons: EnabledButtons {
+                            maximize: false,
+                            ..Default::default()
+                        },
                         ..Default::default()
-                    },
+                    }),
                     ..Default::default()
-                }),
-                ..Default::default()
-            })
-            .set(ImagePlugin::default_nearest())
-            .add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin::default()),))
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-        .add_loading_state(
-            LoadingState::new(AppState::Loading).continue_to_state(AppState::StartMenu),
-        )
-        .add_collection_to_loading_state::<_, MyAssets>(AppState::Loading)
-        .insert_resource(ClearColor(BACKGROUND_COLOR))
-        .add_systems(Startup, (setup, setup_rapier))
-        .add_plugins(StartMenuPlugin)
+                })
+                .set(ImagePlugin::default_nearest())
+                .add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin::default()),))
+                .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+                .init_asset::<PlistAtlas>()
+                .init_asset_loader::<PlistAtlasLoader>()
+                .add_loading_state(
+                    LoadingState::new(AppState::Loading).continue_to_state(AppState::StartMenu),
+                )
+                .add_collection_to_loading_state::<_, MyAssets>(AppState::Loading)
+                .insert_resource(ClearColor(BACKGROUND_COLOR))
+                .add_systems(Startup, (setup, setup_rapier))
+                .add_systems(OnExit(AppState::Loading), card::insert_card_atlas);
+        }
+        AppMode::Headless => {
+            // 无窗口、渲染和音频 也不需要加载资源
+            app.add_plugins((
+                MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(HEADLESS_FRAME_TIME)),
+                LogPlugin::default(),
+            ))
+            .add_systems(Startup, skip_loading);
+        }
+    }
+    app.add_plugins(StartMenuPlugin)
         .add_plugins(LobbyComponent)
         .add_plugins(RoomUIComponent)
-        .run();
+        .add_plugins(HandPlugin)
+        .add_plugins(TablePlugin)
+        .add_plugins(CardCounterPlugin)
+        .add_plugins(SoundPlugin)
+        .add_plugins(StatsPlugin)
+        .add_plugins(ReplayPlugin);
+    app
 }
+
 fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
@@ -62,3 +116,70 @@ fn setup(mut commands: Commands) {
 fn setup_rapier(mut rapier_config: ResMut<RapierConfiguration>) {
     rapier_config.gravity = Vec2::ZERO;
 }
+
+fn skip_loading(mut state: ResMut<NextState<AppState>>) {
+    state.set(AppState::StartMenu);
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_matchbox::prelude::PeerId;
+
+    use super::*;
+    use crate::{
+        common::Socket,
+        lobby::Lobby,
+        player::{Player, Profile},
+    };
+
+    fn state(app: &App) -> AppState {
+        app.world.resource::<State<AppState>>().get().clone()
+    }
+
+    // 测试不连接公网的信令服务器
+    fn headless_app() -> App {
+        let mut app = build_app(AppMode::Headless);
+        app.insert_resource(Socket::offline());
+        app
+    }
+
+    // CI中以无窗口模式运行若干帧 不需要窗口、渲染和资源加载
+    #[test]
+    fn headless_app_runs() {
+        let mut app = headless_app();
+        for _ in 0..30 {
+            app.update();
+        }
+        assert_eq!(state(&app), AppState::StartMenu);
+        assert!(app.world.get_resource::<MyAssets>().is_none());
+    }
+
+    // 无窗口模式没有键盘输入 进入大厅后不能有依赖输入的系统
+    #[test]
+    fn headless_app_enters_lobby() {
+        let mut app = headless_app();
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(state(&app), AppState::StartMenu);
+        // 测试中没有连接 按auto_login的方式直接进入大厅
+        let peer: PeerId =
+            serde_json::from_str("\"00000000-0000-0000-0000-000000000001\"").unwrap();
+        let profile = Profile {
+            uid: 2,
+            nickname: "机器人".to_string(),
+            avatar: Default::default(),
+            voice: Default::default(),
+            coins: 1000,
+        };
+        app.insert_resource(Lobby::new())
+            .insert_resource(Player::new(peer, profile))
+            .world
+            .resource_mut::<NextState<AppState>>()
+            .set(AppState::Lobby);
+        for _ in 0..10 {
+            app.update();
+        }
+        assert_eq!(state(&app), AppState::Lobby);
+    }
+}
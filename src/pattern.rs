@@ -0,0 +1,516 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{card::Card, card_deck::Rank};
+
+// 顺子、连对和飞机最大只能连到A
+pub const MAX_CHAIN_VALUE: u8 = 14;
+const LITTLE_JOKER_VALUE: u8 = 16;
+const BIG_JOKER_VALUE: u8 = 17;
+// 癞子可以代替的点数 3到2 不能当王
+const WILD_MIN_VALUE: u8 = 3;
+const WILD_MAX_VALUE: u8 = 15;
+
+// 每个点数的张数 下标为点数
+type Counts = [u8; BIG_JOKER_VALUE as usize + 1];
+
+// 牌型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PatternKind {
+    /**单张 */
+    Single,
+    /**对子 */
+    Pair,
+    /**三张 */
+    Triple,
+    /**三带一 */
+    TripleWithSingle,
+    /**三带一对 */
+    TripleWithPair,
+    /**顺子 至少5张 */
+    Straight,
+    /**连对 至少3对 */
+    PairStraight,
+    /**飞机 至少两个连续的三张 */
+    Airplane,
+    /**飞机带单张 */
+    AirplaneWithSingles,
+    /**飞机带对子 */
+    AirplaneWithPairs,
+    /**四带二 */
+    FourWithTwo,
+    /**四带两对 */
+    FourWithTwoPairs,
+    /**炸弹 */
+    Bomb,
+    /**王炸 */
+    Rocket,
+}
+
+// 不同玩法的牌型规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PatternRules {
+    // 几副牌 两副牌时炸弹最多8张 四个王才是王炸
+    pub decks: usize,
+    // 癞子玩法中本局的癞子点数
+    pub laizi: Option<Rank>,
+    // 是否允许四带两对
+    pub four_with_two_pairs: bool,
+    // 飞机的翅膀能否带炸弹或王炸
+    pub bomb_wings: bool,
+}
+
+impl Default for PatternRules {
+    fn default() -> Self {
+        Self {
+            decks: 1,
+            laizi: None,
+            four_with_two_pairs: true,
+            bomb_wings: true,
+        }
+    }
+}
+
+// 一手牌的牌型
+// rank为主体部分最大的点数 len为顺子、连对、飞机的节数或炸弹的张数 其他牌型为1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub rank: u8,
+    pub len: u8,
+    // 用癞子代替其他点数凑成的软炸弹
+    pub soft: bool,
+}
+
+// 一种出牌方式 以及其中的癞子分别代替了什么点数
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interpretation {
+    pub pattern: Pattern,
+    pub substitutes: Vec<(Card, Rank)>,
+}
+
+// n个癞子可以代替的点数组合 不考虑顺序
+fn wild_values(n: usize, min: u8) -> Vec<Vec<u8>> {
+    if n == 0 {
+        return vec![vec![]];
+    }
+    (min..=WILD_MAX_VALUE)
+        .flat_map(|value| {
+            wild_values(n - 1, value).into_iter().map(move |mut rest| {
+                rest.insert(0, value);
+                rest
+            })
+        })
+        .collect()
+}
+
+impl Pattern {
+    fn new(kind: PatternKind, rank: u8, len: u8) -> Option<Self> {
+        Some(Self {
+            kind,
+            rank,
+            len,
+            soft: false,
+        })
+    }
+
+    // 识别牌型 有多种方式时取最大的一种 不合规则返回None
+    #[cfg(test)]
+    pub fn classify(cards: &[Card], rules: &PatternRules) -> Option<Self> {
+        Self::interpretations(cards, rules)
+            .into_iter()
+            .map(|interpretation| interpretation.pattern)
+            .max_by_key(Pattern::strength)
+    }
+
+    // 癞子所有可能的代替方式下的牌型 没有癞子时最多一种
+    // 全是癞子时只能当作本身的点数
+    pub fn interpretations(cards: &[Card], rules: &PatternRules) -> Vec<Interpretation> {
+        let (wild, fixed): (Vec<Card>, Vec<Card>) = cards
+            .iter()
+            .partition(|card| Some(card.rank) == rules.laizi && cards.len() > 1);
+        let mut counts: Counts = Default::default();
+        for card in fixed.iter() {
+            counts[card.rank.value() as usize] += 1;
+        }
+        let choices = if wild.len() == cards.len() {
+            vec![wild.iter().map(|card| card.rank.value()).collect()]
+        } else {
+            wild_values(wild.len(), WILD_MIN_VALUE)
+        };
+        let mut result: Vec<Interpretation> = vec![];
+        for values in choices {
+            let mut counts = counts;
+            for value in values.iter() {
+                counts[*value as usize] += 1;
+            }
+            // 每个点数最多4张 两副牌8张
+            if counts.iter().any(|count| *count as usize > 4 * rules.decks) {
+                continue;
+            }
+            let Some(mut pattern) = Self::from_counts(&counts, cards.len(), rules) else {
+                continue;
+            };
+            let substitutes: Vec<(Card, Rank)> = wild
+                .iter()
+                .zip(values.iter())
+                .filter(|(card, value)| card.rank.value() != **value)
+                .filter_map(|(card, value)| Some((*card, Rank::from_value(*value)?)))
+                .collect();
+            pattern.soft = pattern.kind == PatternKind::Bomb && !substitutes.is_empty();
+            if result.iter().all(|r| r.pattern != pattern) {
+                result.push(Interpretation {
+                    pattern,
+                    substitutes,
+                });
+            }
+        }
+        result
+    }
+
+    // 按每个点数的张数识别牌型
+    fn from_counts(counts: &Counts, total: usize, rules: &PatternRules) -> Option<Self> {
+        use PatternKind::*;
+        // 王炸需要所有的王
+        let jokers = rules.decks as u8;
+        if total == 2 * rules.decks
+            && counts[LITTLE_JOKER_VALUE as usize] == jokers
+            && counts[BIG_JOKER_VALUE as usize] == jokers
+        {
+            return Self::new(Rocket, BIG_JOKER_VALUE, total as u8);
+        }
+        // 按张数从多到少、点数从大到小排列
+        let mut groups: Vec<(u8, u8)> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(value, count)| (*count, value as u8))
+            .collect();
+        groups.sort_by(|a, b| b.cmp(a));
+        let (top_count, top_value) = *groups.first()?;
+        if groups.len() == 1 {
+            return match top_count {
+                1 => Self::new(Single, top_value, 1),
+                2 => Self::new(Pair, top_value, 1),
+                3 => Self::new(Triple, top_value, 1),
+                count => Self::new(Bomb, top_value, count),
+            };
+        }
+        match (total, top_count) {
+            (4, 3) => return Self::new(TripleWithSingle, top_value, 1),
+            (5, 3) if groups[1].0 == 2 => return Self::new(TripleWithPair, top_value, 1),
+            _ => {}
+        }
+        if let Some(pattern) = Self::chain(&groups, total, rules) {
+            return Some(pattern);
+        }
+        let wings = &groups[1..];
+        match (total, top_count) {
+            (6, 4) => Self::new(FourWithTwo, top_value, 1),
+            (8, 4)
+                if rules.four_with_two_pairs
+                    && wings.len() == 2
+                    && wings.iter().all(|(count, _)| *count == 2) =>
+            {
+                Self::new(FourWithTwoPairs, top_value, 1)
+            }
+            _ => None,
+        }
+    }
+
+    // 顺子、连对和飞机
+    fn chain(groups: &[(u8, u8)], total: usize, rules: &PatternRules) -> Option<Self> {
+        use PatternKind::*;
+        let consecutive = |values: &mut Vec<u8>| {
+            values.sort();
+            values.last().is_some_and(|max| *max <= MAX_CHAIN_VALUE)
+                && values.windows(2).all(|w| w[1] == w[0] + 1)
+        };
+        let mut values: Vec<u8> = groups.iter().map(|(_, value)| *value).collect();
+        let len = values.len() as u8;
+        if groups.iter().all(|(count, _)| *count == 1) && total >= 5 && consecutive(&mut values) {
+            return Self::new(Straight, values[values.len() - 1], len);
+        }
+        if groups.iter().all(|(count, _)| *count == 2) && len >= 3 && consecutive(&mut values) {
+            return Self::new(PairStraight, values[values.len() - 1], len);
+        }
+        // 从最长、最大的连续三张开始找 剩下的牌作为翅膀
+        let mut triples: Vec<u8> = groups
+            .iter()
+            .filter(|(count, value)| *count >= 3 && *value <= MAX_CHAIN_VALUE)
+            .map(|(_, value)| *value)
+            .collect();
+        triples.sort();
+        for size in (2..=triples.len()).rev() {
+            for window in triples.windows(size).rev() {
+                if !window.windows(2).all(|w| w[1] == w[0] + 1) {
+                    continue;
+                }
+                let high = window[size - 1];
+                let size = size as u8;
+                let wings: Vec<u8> = groups
+                    .iter()
+                    .map(|(count, value)| {
+                        if window.contains(value) {
+                            count - 3
+                        } else {
+                            *count
+                        }
+                    })
+                    .filter(|count| *count > 0)
+                    .collect();
+                // 翅膀中整个的炸弹或王炸
+                let jokers = groups
+                    .iter()
+                    .filter(|(count, value)| {
+                        *value >= LITTLE_JOKER_VALUE && *count as usize == rules.decks
+                    })
+                    .count();
+                let bomb_wing = jokers == 2
+                    || groups
+                        .iter()
+                        .any(|(count, value)| *count >= 4 && !window.contains(value));
+                if bomb_wing && !rules.bomb_wings {
+                    continue;
+                }
+                let wing_cards: usize = wings.iter().map(|count| *count as usize).sum();
+                if wing_cards == 0 {
+                    return Self::new(Airplane, high, size);
+                }
+                if wing_cards == size as usize {
+                    return Self::new(AirplaneWithSingles, high, size);
+                }
+                if wing_cards == 2 * size as usize && wings.iter().all(|count| count % 2 == 0) {
+                    return Self::new(AirplaneWithPairs, high, size);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn is_bomb(&self) -> bool {
+        matches!(self.kind, PatternKind::Bomb | PatternKind::Rocket)
+    }
+
+    // 同一手牌有多种识别方式时的大小 王炸、炸弹优先
+    pub fn strength(&self) -> (bool, bool, u8, bool, u8, u8) {
+        let bomb_len = if self.is_bomb() { self.len } else { 0 };
+        (
+            self.kind == PatternKind::Rocket,
+            self.is_bomb(),
+            bomb_len,
+            !self.soft,
+            self.len,
+            self.rank,
+        )
+    }
+
+    // 炸弹的翻倍 癞子玩法中软炸翻倍 硬炸和王炸翻四倍 其他玩法都是翻倍
+    pub fn multiple(&self, laizi: bool) -> u32 {
+        match (self.is_bomb(), laizi && !self.soft) {
+            (false, _) => 1,
+            (true, false) => 2,
+            (true, true) => 4,
+        }
+    }
+
+    // 能否压过上一手牌 同牌型同长度比点数 炸弹压一切非炸弹
+    // 张数多的炸弹大 张数相同时硬炸大于软炸 王炸最大
+    pub fn beats(&self, other: &Pattern) -> bool {
+        use PatternKind::*;
+        match (self.kind, other.kind) {
+            (Rocket, _) => other.kind != Rocket,
+            (_, Rocket) => false,
+            (Bomb, Bomb) => {
+                (self.len, !self.soft, self.rank) > (other.len, !other.soft, other.rank)
+            }
+            (Bomb, _) => true,
+            (_, Bomb) => false,
+            _ => self.kind == other.kind && self.len == other.len && self.rank > other.rank,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            PatternKind::Single => "单张",
+            PatternKind::Pair => "对子",
+            PatternKind::Triple => "三张",
+            PatternKind::TripleWithSingle => "三带一",
+            PatternKind::TripleWithPair => "三带一对",
+            PatternKind::Straight => "顺子",
+            PatternKind::PairStraight => "连对",
+            PatternKind::Airplane => "飞机",
+            PatternKind::AirplaneWithSingles => "飞机带单",
+            PatternKind::AirplaneWithPairs => "飞机带对",
+            PatternKind::FourWithTwo => "四带二",
+            PatternKind::FourWithTwoPairs => "四带两对",
+            PatternKind::Bomb if self.soft => "软炸",
+            PatternKind::Bomb => "炸弹",
+            PatternKind::Rocket => "王炸",
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::card_deck::{Rank, Suit};
+    use PatternKind::*;
+
+    // 用点数写牌 花色轮流分配 0和1表示小王和大王
+    pub fn cards(ranks: &str) -> Vec<Card> {
+        let suits = [Suit::Spade, Suit::Heart, Suit::Club, Suit::Diamond];
+        ranks
+            .chars()
+            .enumerate()
+            .map(|(index, c)| {
+                let rank = match c {
+                    '3' => Rank::Three,
+                    '4' => Rank::Four,
+                    '5' => Rank::Five,
+                    '6' => Rank::Six,
+                    '7' => Rank::Seven,
+                    '8' => Rank::Eight,
+                    '9' => Rank::Nine,
+                    'T' => Rank::Ten,
+                    'J' => Rank::Jack,
+                    'Q' => Rank::Queen,
+                    'K' => Rank::King,
+                    'A' => Rank::Ace,
+                    '2' => Rank::Two,
+                    '0' => return Card::new(Suit::Joker, Rank::LittleJoker),
+                    '1' => return Card::new(Suit::Joker, Rank::BigJoker),
+                    _ => panic!("{}", c),
+                };
+                Card::new(suits[index % 4], rank)
+            })
+            .collect()
+    }
+
+    fn kind(ranks: &str) -> Option<(PatternKind, u8, u8)> {
+        Pattern::classify(&cards(ranks), &PatternRules::default()).map(|p| (p.kind, p.rank, p.len))
+    }
+
+    #[test]
+    fn classifies_basic_patterns() {
+        assert_eq!(kind("3"), Some((Single, 3, 1)));
+        assert_eq!(kind("22"), Some((Pair, 15, 1)));
+        assert_eq!(kind("KKK"), Some((Triple, 13, 1)));
+        assert_eq!(kind("KKK3"), Some((TripleWithSingle, 13, 1)));
+        assert_eq!(kind("KKK33"), Some((TripleWithPair, 13, 1)));
+        assert_eq!(kind("7777"), Some((Bomb, 7, 4)));
+        assert_eq!(kind("01"), Some((Rocket, 17, 2)));
+        assert_eq!(kind("777735"), Some((FourWithTwo, 7, 1)));
+        assert_eq!(kind("77773355"), Some((FourWithTwoPairs, 7, 1)));
+        assert_eq!(kind(""), None);
+        assert_eq!(kind("34"), None);
+        assert_eq!(kind("0"), Some((Single, 16, 1)));
+    }
+
+    #[test]
+    fn classifies_chains() {
+        assert_eq!(kind("34567"), Some((Straight, 7, 5)));
+        assert_eq!(kind("TJQKA"), Some((Straight, 14, 5)));
+        assert_eq!(kind("JQKA2"), None);
+        assert_eq!(kind("3456"), None);
+        assert_eq!(kind("334455"), Some((PairStraight, 5, 3)));
+        assert_eq!(kind("3344"), None);
+        assert_eq!(kind("333444"), Some((Airplane, 4, 2)));
+        assert_eq!(kind("33344479"), Some((AirplaneWithSingles, 4, 2)));
+        assert_eq!(kind("3334447799"), Some((AirplaneWithPairs, 4, 2)));
+        assert_eq!(kind("222AAA34"), None);
+        // 四个三张中只有后三个连续 第一个拆成翅膀
+        assert_eq!(kind("333555666777"), Some((AirplaneWithSingles, 7, 3)));
+    }
+
+    #[test]
+    fn compares_patterns() {
+        let pattern = |ranks| Pattern::classify(&cards(ranks), &PatternRules::default()).unwrap();
+        assert!(pattern("4").beats(&pattern("3")));
+        assert!(!pattern("3").beats(&pattern("4")));
+        assert!(pattern("2").beats(&pattern("A")));
+        assert!(!pattern("44").beats(&pattern("3")));
+        assert!(pattern("45678").beats(&pattern("34567")));
+        assert!(!pattern("456789").beats(&pattern("34567")));
+        assert!(pattern("3333").beats(&pattern("22")));
+        assert!(pattern("4444").beats(&pattern("3333")));
+        assert!(pattern("01").beats(&pattern("2222")));
+        assert!(!pattern("2222").beats(&pattern("01")));
+    }
+
+    #[test]
+    fn two_decks_rank_bombs_by_size() {
+        let rules = PatternRules {
+            decks: 2,
+            ..Default::default()
+        };
+        let pattern = |ranks| Pattern::classify(&cards(ranks), &rules);
+        assert_eq!(pattern("01").map(|p| p.kind), None);
+        assert_eq!(pattern("11").map(|p| p.kind), Some(Pair));
+        let rocket = pattern("0011").unwrap();
+        assert_eq!(rocket.kind, Rocket);
+        let five = pattern("33333").unwrap();
+        let eight = pattern("33333333").unwrap();
+        assert_eq!((five.kind, five.len), (Bomb, 5));
+        assert!(five.beats(&pattern("2222").unwrap()));
+        assert!(eight.beats(&pattern("2222222").unwrap()));
+        assert!(!pattern("AAAAA").unwrap().beats(&pattern("333333").unwrap()));
+        assert!(rocket.beats(&eight));
+    }
+
+    #[test]
+    fn laizi_substitutes_any_rank_but_jokers() {
+        let rules = PatternRules {
+            laizi: Some(Rank::Nine),
+            ..Default::default()
+        };
+        let pattern = |ranks| Pattern::classify(&cards(ranks), &rules);
+        // 一张癞子凑成顺子
+        assert_eq!(
+            pattern("34569").map(|p| (p.kind, p.rank)),
+            Some((Straight, 7))
+        );
+        // 癞子单出只能当本身
+        assert_eq!(pattern("9").map(|p| p.rank), Some(9));
+        assert_eq!(pattern("99").map(|p| (p.kind, p.rank)), Some((Pair, 9)));
+        // 不能当王
+        assert_eq!(pattern("09"), None);
+        let soft = pattern("KKK9").unwrap();
+        assert_eq!((soft.kind, soft.rank, soft.soft), (Bomb, 13, true));
+        let hard = pattern("3333").unwrap();
+        assert!(!hard.soft);
+        assert!(hard.beats(&soft));
+        assert!(!soft.beats(&hard));
+        assert!(pattern("AAA9").unwrap().beats(&soft));
+        assert_eq!(soft.multiple(true), 2);
+        assert_eq!(hard.multiple(true), 4);
+        assert_eq!(hard.multiple(false), 2);
+        // 同一手牌的不同识别方式
+        let kinds: Vec<PatternKind> = Pattern::interpretations(&cards("KK99"), &rules)
+            .into_iter()
+            .map(|i| i.pattern.kind)
+            .collect();
+        assert!(kinds.contains(&Bomb));
+        assert!(kinds.contains(&TripleWithSingle));
+        let substitutes = &Pattern::interpretations(&cards("34569"), &rules)[0].substitutes;
+        assert_eq!(substitutes.len(), 1);
+        assert_eq!(substitutes[0].0.rank, Rank::Nine);
+    }
+
+    #[test]
+    fn room_rules_restrict_wings() {
+        let strict = PatternRules {
+            four_with_two_pairs: false,
+            bomb_wings: false,
+            ..Default::default()
+        };
+        let loose = PatternRules::default();
+        let kind = |ranks, rules| Pattern::classify(&cards(ranks), rules).map(|p| p.kind);
+        assert_eq!(kind("77773355", &strict), None);
+        assert_eq!(kind("777735", &strict), Some(FourWithTwo));
+        assert_eq!(kind("3334445555", &loose), Some(AirplaneWithPairs));
+        assert_eq!(kind("3334445555", &strict), None);
+        assert_eq!(kind("33344401", &loose), Some(AirplaneWithSingles));
+        assert_eq!(kind("33344401", &strict), None);
+        assert_eq!(kind("3334447799", &strict), Some(AirplaneWithPairs));
+    }
+}
@@ -1,25 +1,132 @@
 use bevy::prelude::*;
 use bevy_matchbox::prelude::*;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::card::Card;
+use crate::{card::Card, stats::STATS_SERVER_UID, storage};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+// 本地保存个人资料的键
+const PROFILE_KEY: &str = "profile";
+// 昵称最多几个字
+pub const NICKNAME_MAX_CHARS: usize = 8;
+// 新玩家的初始金币
+const INITIAL_COINS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub enum RoomState {
+    #[default]
     Free,
     Waiting,
     Gaming,
 }
 
-impl Default for RoomState {
-    fn default() -> Self {
-        RoomState::Free
+impl RoomState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RoomState::Free => "空闲",
+            RoomState::Waiting => "等待中",
+            RoomState::Gaming => "游戏中",
+        }
+    }
+}
+
+// 头像 男孩或女孩
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Avatar {
+    #[default]
+    Male,
+    Female,
+}
+
+impl Avatar {
+    // public_ui图集中的头像
+    pub fn head_frame(&self) -> &'static str {
+        match self {
+            Avatar::Male => "head_img_male.png",
+            Avatar::Female => "head_img_female.png",
+        }
+    }
+
+    // public_ui图集中的性别标志
+    pub fn sex_frame(&self) -> &'static str {
+        match self {
+            Avatar::Male => "sex_male.png",
+            Avatar::Female => "sex_female.png",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            Avatar::Male => Avatar::Female,
+            Avatar::Female => Avatar::Male,
+        }
+    }
+}
+
+// 语音包 牌局中叫地主和出牌的配音
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum VoicePack {
+    #[default]
+    Male,
+    Female,
+}
+
+impl VoicePack {
+    pub fn label(&self) -> &'static str {
+        match self {
+            VoicePack::Male => "男声",
+            VoicePack::Female => "女声",
+        }
+    }
+
+    pub fn toggled(&self) -> Self {
+        match self {
+            VoicePack::Male => VoicePack::Female,
+            VoicePack::Female => VoicePack::Male,
+        }
+    }
+}
+
+// 个人资料 保存在本地 加入房间时随Player一起发给房主 其他座位据此显示名字和头像
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    // 本地生成的玩家编号 昵称可以改 战绩和排行榜按编号区分玩家
+    #[serde(default)]
+    pub uid: u64,
+    pub nickname: String,
+    pub avatar: Avatar,
+    pub voice: VoicePack,
+    // 金币余额
+    pub coins: u64,
+}
+
+impl Profile {
+    // 读取本地资料 第一次运行时生成一个游客昵称和编号并保存
+    pub fn load() -> Self {
+        let mut profile = storage::load(PROFILE_KEY).unwrap_or_else(|| Self {
+            uid: 0,
+            nickname: format!("游客{:04}", rand::thread_rng().gen_range(0..10000)),
+            avatar: Avatar::default(),
+            voice: VoicePack::default(),
+            coins: INITIAL_COINS,
+        });
+        // 第一次运行或旧版本保存的资料没有编号 排行榜服务器的编号留给服务器
+        if profile.uid <= STATS_SERVER_UID {
+            profile.uid = rand::thread_rng().gen_range(STATS_SERVER_UID + 1..u64::MAX);
+            profile.save();
+        }
+        profile
+    }
+
+    pub fn save(&self) {
+        storage::save(PROFILE_KEY, self);
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Resource, Component)]
 pub struct Player {
     pub id: PeerId,
+    pub profile: Profile,
     pub room_state: Option<RoomState>,
     pub room_index: Option<i32>,
     pub current_round: bool,
@@ -35,13 +142,26 @@ impl PartialEq for Player {
 // impl Eq for Player {}
 
 impl Player {
-    pub fn new(peer: PeerId) -> Self {
+    pub fn new(peer: PeerId, profile: Profile) -> Self {
         Self {
             id: peer,
+            profile,
             room_index: None,
             current_round: false,
             room_state: None,
-            hand_cards: Vec::with_capacity(51 / 3),
+            hand_cards: Vec::new(),
+        }
+    }
+
+    // 显示名称 没有昵称时使用PeerId的前7位
+    pub fn name(&self) -> String {
+        if !self.profile.nickname.is_empty() {
+            return self.profile.nickname.clone();
         }
+        self.id
+            .to_string()
+            .get(0..7)
+            .unwrap_or("player")
+            .to_string()
     }
 }
@@ -0,0 +1,640 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::Card,
+    card_deck::Rank,
+    common::{despawn_screen, in_room, AppMode, AppState, MyAssets},
+    game::{Game, GameAction, GameResult, Phase},
+    player::{Player, RoomState},
+    room::{Room, RoomPlayer, RoomRules},
+    storage,
+};
+
+// 本地保存回放的键
+const REPLAYS_KEY: &str = "replays";
+// 最多保存最近几局
+const REPLAY_LIMIT: usize = 20;
+// 一倍速时每步的间隔
+const REPLAY_STEP_SECONDS: f32 = 1.5;
+// 可选的播放速度
+const REPLAY_SPEEDS: [f32; 4] = [0.5, 1., 2., 4.];
+
+pub struct ReplayPlugin;
+
+// 一局的回放 只保存发牌和每一步操作 回放时从发牌开始重新执行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub players: Vec<Player>,
+    // 录制者的座位 回放时从这个座位看
+    pub seat: usize,
+    pub rules: RoomRules,
+    pub seed: u64,
+    // 最后一次发牌后第一个叫地主的座位
+    pub first: usize,
+    pub hands: Vec<Vec<Card>>,
+    pub bottom: Vec<Card>,
+    pub laizi: Option<Rank>,
+    // 发牌时的明牌 叫地主阶段的明牌记在操作里
+    pub shown: Vec<Option<u32>>,
+    pub landlord: usize,
+    // 叫地主、加倍和出牌 按先后顺序
+    pub actions: Vec<(usize, GameAction)>,
+    pub result: GameResult,
+}
+
+impl Replay {
+    // 从结束的牌局记录发牌和操作顺序 发牌由房主在牌局结束时发来
+    // 没人叫地主重新发牌之前的操作不记录
+    pub fn new(game: &Game, players: Vec<Player>, seat: usize) -> Option<Self> {
+        if game.phase != Phase::Finished {
+            return None;
+        }
+        let result = game.result.clone()?;
+        let landlord = game.landlord?;
+        let seats = game.seats();
+        if game.deal.len() != seats {
+            return None;
+        }
+        // 叫地主阶段的明牌按发生时已经叫过的次数插到叫地主中间
+        let mut bids = vec![];
+        for index in 0..=game.bids.len() {
+            bids.extend(
+                game.shows
+                    .iter()
+                    .filter(|(at, _)| *at == index)
+                    .map(|(_, seat)| (*seat, GameAction::ShowHand)),
+            );
+            if let Some((seat, bid)) = game.bids.get(index) {
+                bids.push((*seat, GameAction::Bid(*bid)));
+            }
+        }
+        // 发牌时的明牌 叫地主阶段的明牌回放到那一步才翻开
+        let mut shown = game.shown.clone();
+        for (_, seat) in game.shows.iter() {
+            shown[*seat] = None;
+        }
+        // 农民从地主下家开始依次加倍 最后是地主
+        let doubles = (1..=seats)
+            .map(|offset| (landlord + offset) % seats)
+            .filter_map(|seat| game.doubles[seat].map(|double| (seat, GameAction::Double(double))));
+        let plays = game.plays.iter().map(|record| {
+            let action = match record.pattern {
+                Some(_) => GameAction::Play(record.cards.clone()),
+                None => GameAction::Pass,
+            };
+            (record.seat, action)
+        });
+        Some(Self {
+            players,
+            seat,
+            rules: game.rules.clone(),
+            seed: game.seed,
+            first: game.first,
+            hands: game.deal.clone(),
+            bottom: game.bottom.clone(),
+            laizi: game.laizi,
+            shown,
+            landlord,
+            actions: bids.into_iter().chain(doubles).chain(plays).collect(),
+            result,
+        })
+    }
+
+    // 房间里结束的牌局
+    pub fn from_room(room: &Room, local: &Player) -> Option<Self> {
+        let players = room
+            .players
+            .iter()
+            .map(|room_player| {
+                room_player.as_ref().map(|room_player| Player {
+                    hand_cards: vec![],
+                    ..room_player.player.clone()
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Self::new(room.game.as_ref()?, players, room.seat_of(local.id)?)
+    }
+
+    // 执行前step步操作后的牌局
+    pub fn game_at(&self, step: usize) -> Game {
+        let mut game = Game::new(self.rules.clone(), self.first);
+        game.seed = self.seed;
+        game.hands = self.hands.clone();
+        game.deal = self.hands.clone();
+        game.bottom = self.bottom.clone();
+        game.laizi = self.laizi;
+        game.shown = self.shown.clone();
+        for (seat, action) in self.actions.iter().take(step) {
+            // 记录的操作都已经由房主校验过
+            if let Err(err) = game.apply(*seat, action) {
+                warn!("回放第{}步失败: {}", step, err);
+                break;
+            }
+        }
+        game
+    }
+
+    pub fn name(&self, seat: usize) -> String {
+        self.players
+            .get(seat)
+            .map(|player| player.name())
+            .unwrap_or_default()
+    }
+
+    // 第step步做了什么 0为发牌
+    fn describe(&self, step: usize) -> String {
+        let Some((seat, action)) = step.checked_sub(1).and_then(|i| self.actions.get(i)) else {
+            return "发牌".to_string();
+        };
+        let before = self.game_at(step - 1);
+        let label = match action {
+            GameAction::Bid(bid) => bid.label(before.called()),
+            GameAction::Double(double) => double.label().to_string(),
+            GameAction::Play(cards) => {
+                let pattern = before
+                    .check_play(*seat, cards)
+                    .map(|interpretation| interpretation.pattern.name())
+                    .unwrap_or_default();
+                let cards: Vec<String> = cards.iter().map(|card| card.label()).collect();
+                format!("{} {}", pattern, cards.join(" "))
+            }
+            GameAction::Pass => "不出".to_string(),
+            GameAction::ShowHand => "明牌".to_string(),
+        };
+        format!("{} {}", self.name(*seat), label)
+    }
+
+    // 用回放中的牌局组成一个房间 复用牌桌的显示 所有人的手牌都翻开
+    fn room_at(&self, step: usize, local: &Player) -> Room {
+        let mut game = self.game_at(step);
+        game.shown = game
+            .shown
+            .iter()
+            .map(|shown| Some(shown.unwrap_or(1)))
+            .collect();
+        let players: Vec<Option<RoomPlayer>> = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(seat, player)| {
+                let mut player = player.clone();
+                // 录制者的座位换成自己 牌桌按自己的位置排列座位
+                if seat == self.seat {
+                    player.id = local.id;
+                }
+                Some(RoomPlayer {
+                    player,
+                    room_position: seat as i8,
+                    ready: true,
+                    show_hand: false,
+                })
+            })
+            .collect();
+        let owner = players[self.seat].clone().expect("录制者在座位上");
+        let mut room = Room::new(owner.player, 0);
+        room.players = players;
+        room.rules = self.rules.clone();
+        room.state = RoomState::Gaming;
+        room.game = Some(game);
+        room
+    }
+}
+
+// 本地保存的回放 最新的在最后
+#[derive(Debug, Default, Resource, Serialize, Deserialize)]
+pub struct ReplayStore {
+    pub replays: Vec<Replay>,
+    // 已经录制过的牌局 (房间号, 洗牌种子) 避免重复录制
+    #[serde(skip)]
+    recorded: Option<(u32, u64)>,
+}
+
+impl ReplayStore {
+    pub fn load() -> Self {
+        storage::load(REPLAYS_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(REPLAYS_KEY, self);
+    }
+
+    fn push(&mut self, replay: Replay) {
+        self.replays.push(replay);
+        if self.replays.len() > REPLAY_LIMIT {
+            self.replays.remove(0);
+        }
+    }
+}
+
+// 正在回放的牌局
+#[derive(Resource)]
+pub struct ReplayViewer {
+    pub replay: Replay,
+    // 已经执行的操作数
+    pub step: usize,
+    pub playing: bool,
+    // REPLAY_SPEEDS中的位置
+    pub speed: usize,
+    pub timer: Timer,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            step: 0,
+            playing: true,
+            speed: 1,
+            timer: Timer::from_seconds(REPLAY_STEP_SECONDS, TimerMode::Repeating),
+        }
+    }
+
+    fn speed(&self) -> f32 {
+        REPLAY_SPEEDS[self.speed]
+    }
+
+    fn steps(&self) -> usize {
+        self.replay.actions.len()
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayComponent;
+
+#[derive(Component)]
+pub enum ReplayButton {
+    Back,
+    Forward,
+    TogglePlay,
+    Speed,
+    Exit,
+}
+
+// 第几步和这一步的操作
+#[derive(Component)]
+pub struct ReplayStepText;
+
+// 每个座位的名字、身份和剩余牌数
+#[derive(Component)]
+pub struct ReplaySeatsText;
+
+#[derive(Component)]
+pub struct ReplayPlayText;
+
+#[derive(Component)]
+pub struct ReplaySpeedText;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayStore::load())
+            .add_systems(Update, record_replay.run_if(in_room))
+            .add_systems(
+                OnEnter(AppState::Replay),
+                (enter, setup.run_if(resource_equals(AppMode::Windowed))).chain(),
+            )
+            .add_systems(
+                Update,
+                (replay_buttons, auto_play, refresh_replay)
+                    .chain()
+                    .run_if(in_state(AppState::Replay))
+                    .run_if(resource_exists::<ReplayViewer>()),
+            )
+            .add_systems(
+                OnExit(AppState::Replay),
+                (exit, despawn_screen::<ReplayComponent>),
+            );
+    }
+}
+
+// 牌局结束后保存回放
+fn record_replay(room: Res<Room>, local: Res<Player>, mut store: ResMut<ReplayStore>) {
+    if !room.is_changed() {
+        return;
+    }
+    let Some(game) = &room.game else {
+        return;
+    };
+    let key = (room.code, game.seed);
+    if store.recorded == Some(key) {
+        return;
+    }
+    let Some(replay) = Replay::from_room(&room, &local) else {
+        return;
+    };
+    store.recorded = Some(key);
+    store.push(replay);
+    store.save();
+}
+
+fn enter(mut commands: Commands, viewer: Res<ReplayViewer>, local: Res<Player>) {
+    commands.insert_resource(viewer.replay.room_at(viewer.step, &local));
+}
+
+fn exit(mut commands: Commands) {
+    commands.remove_resource::<Room>();
+    commands.remove_resource::<ReplayViewer>();
+}
+
+fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 24.,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", text_style.clone()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(3.),
+            right: Val::Percent(2.),
+            ..Default::default()
+        }),
+        ReplaySeatsText,
+        ReplayComponent,
+    ));
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(10.),
+                    width: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ReplayComponent,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        color: Color::GOLD,
+                        ..text_style.clone()
+                    },
+                ),
+                ReplayStepText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(12.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (label, button) in [
+                        ("后退", ReplayButton::Back),
+                        ("", ReplayButton::TogglePlay),
+                        ("前进", ReplayButton::Forward),
+                        ("", ReplayButton::Speed),
+                        ("返回大厅", ReplayButton::Exit),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                                        ..Default::default()
+                                    },
+                                    background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                                    ..Default::default()
+                                },
+                                ReplayComponent,
+                            ))
+                            .with_children(|parent| {
+                                let mut text = parent
+                                    .spawn(TextBundle::from_section(label, text_style.clone()));
+                                match button {
+                                    ReplayButton::TogglePlay => {
+                                        text.insert(ReplayPlayText);
+                                    }
+                                    ReplayButton::Speed => {
+                                        text.insert(ReplaySpeedText);
+                                    }
+                                    _ => {}
+                                }
+                            })
+                            .insert(button);
+                    }
+                });
+        });
+}
+
+// 点击后退、前进、播放暂停、调整速度和返回大厅
+fn replay_buttons(
+    query: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            ReplayButton::Back => {
+                viewer.playing = false;
+                viewer.step = viewer.step.saturating_sub(1);
+            }
+            ReplayButton::Forward => {
+                viewer.playing = false;
+                viewer.step = (viewer.step + 1).min(viewer.steps());
+            }
+            ReplayButton::TogglePlay => {
+                // 播放到最后再点从头开始
+                if !viewer.playing && viewer.step == viewer.steps() {
+                    viewer.step = 0;
+                }
+                viewer.playing = !viewer.playing;
+            }
+            ReplayButton::Speed => viewer.speed = (viewer.speed + 1) % REPLAY_SPEEDS.len(),
+            ReplayButton::Exit => state.set(AppState::Lobby),
+        }
+    }
+}
+
+// 播放中按速度前进
+fn auto_play(time: Res<Time>, mut viewer: ResMut<ReplayViewer>) {
+    if !viewer.playing {
+        return;
+    }
+    let delta = time.delta().mul_f32(viewer.speed());
+    if viewer.timer.tick(delta).just_finished() {
+        viewer.step = (viewer.step + 1).min(viewer.steps());
+        if viewer.step == viewer.steps() {
+            viewer.playing = false;
+        }
+    }
+}
+
+// 步数变化时重建房间 牌桌的显示随房间刷新
+fn refresh_replay(
+    viewer: Res<ReplayViewer>,
+    local: Res<Player>,
+    mut room: ResMut<Room>,
+    mut step_text: Query<
+        &mut Text,
+        (
+            With<ReplayStepText>,
+            Without<ReplaySeatsText>,
+            Without<ReplayPlayText>,
+            Without<ReplaySpeedText>,
+        ),
+    >,
+    mut seats_text: Query<
+        &mut Text,
+        (
+            With<ReplaySeatsText>,
+            Without<ReplayPlayText>,
+            Without<ReplaySpeedText>,
+        ),
+    >,
+    mut play_text: Query<&mut Text, (With<ReplayPlayText>, Without<ReplaySpeedText>)>,
+    mut speed_text: Query<&mut Text, With<ReplaySpeedText>>,
+    added: Query<(), Added<ReplayStepText>>,
+) {
+    if !viewer.is_changed() && added.is_empty() {
+        return;
+    }
+    for mut text in play_text.iter_mut() {
+        text.sections[0].value = if viewer.playing { "暂停" } else { "播放" }.to_string();
+    }
+    for mut text in speed_text.iter_mut() {
+        text.sections[0].value = format!("{}倍速", viewer.speed());
+    }
+    let steps_changed = room
+        .game
+        .as_ref()
+        .is_none_or(|game| replayed_steps(game) != viewer.step);
+    if !steps_changed && added.is_empty() {
+        return;
+    }
+    let replay = &viewer.replay;
+    if steps_changed {
+        *room = replay.room_at(viewer.step, &local);
+    }
+    let Some(game) = &room.game else {
+        return;
+    };
+    for mut text in step_text.iter_mut() {
+        let mut line = format!(
+            "{}/{}  {}",
+            viewer.step,
+            viewer.steps(),
+            replay.describe(viewer.step)
+        );
+        if let Some(result) = game.result.as_ref() {
+            line += &format!(
+                "  {}胜 {}倍{}",
+                if result.landlord_won {
+                    "地主"
+                } else {
+                    "农民"
+                },
+                result.multiple,
+                if result.spring { " 春天" } else { "" }
+            );
+        }
+        text.sections[0].value = line;
+    }
+    for mut text in seats_text.iter_mut() {
+        text.sections[0].value = (0..game.seats())
+            .map(|seat| {
+                let role = match game.landlord {
+                    Some(landlord) if landlord == seat => "地主",
+                    Some(_) => "农民",
+                    None => "",
+                };
+                let mut line = format!(
+                    "{} {} 剩{}张",
+                    replay.name(seat),
+                    role,
+                    game.hands[seat].len()
+                );
+                if let Some(double) = game.doubles[seat] {
+                    line += &format!(" {}", double.label());
+                }
+                if let Some(result) = game.result.as_ref() {
+                    line += &format!(" {:+}", result.scores[seat]);
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+// 房间里的牌局已经执行了多少步
+fn replayed_steps(game: &Game) -> usize {
+    game.bids.len() + game.shows.len() + game.doubles.iter().flatten().count() + game.plays.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Doubling, SHOW_HAND_MULTIPLE, SHOW_HAND_START_MULTIPLE};
+
+    fn finish(game: &mut Game) {
+        while game.phase != Phase::Finished {
+            let action = match game.phase {
+                Phase::Bidding => GameAction::Bid(game.bid_options()[0]),
+                Phase::Doubling => GameAction::Double(Doubling::Double),
+                _ => game.timeout_action(),
+            };
+            game.apply(game.turn, &action).unwrap();
+        }
+    }
+
+    #[test]
+    fn replays_finished_game() {
+        let rules = RoomRules {
+            doubling: true,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules, 0);
+        finish(&mut game);
+        let replay = Replay::new(&game, vec![], 0).unwrap();
+        assert_eq!(replay.game_at(0).hands.concat().len(), 51);
+        let replayed = replay.game_at(replay.actions.len());
+        assert_eq!(replayed.phase, Phase::Finished);
+        assert_eq!(replayed.hands, game.hands);
+        assert_eq!(replayed_steps(&replayed), replay.actions.len());
+        let result = replayed.result.unwrap();
+        assert_eq!(result.scores, replay.result.scores);
+        assert_eq!(result.multiple, replay.result.multiple);
+
+        // 没有房主发来的发牌记录时不能录制
+        game.deal.clear();
+        assert!(Replay::new(&game, vec![], 0).is_none());
+
+        // 开局明牌从发牌就翻开 叫地主中途的明牌回放到那一步才翻开
+        let rules = RoomRules {
+            show_hand: true,
+            ..Default::default()
+        };
+        let mut game = Game::new(rules, 0);
+        game.show_hand(0, SHOW_HAND_START_MULTIPLE).unwrap();
+        game.apply(0, &GameAction::Bid(game.bid_options()[0]))
+            .unwrap();
+        game.apply(2, &GameAction::ShowHand).unwrap();
+        finish(&mut game);
+        let replay = Replay::new(&game, vec![], 0).unwrap();
+        assert_eq!(
+            replay.shown,
+            vec![Some(SHOW_HAND_START_MULTIPLE), None, None]
+        );
+        assert!(matches!(replay.actions[1], (2, GameAction::ShowHand)));
+        assert_eq!(replay.game_at(1).shown[2], None);
+        assert_eq!(replay.game_at(2).shown[2], Some(SHOW_HAND_MULTIPLE));
+        let replayed = replay.game_at(replay.actions.len());
+        assert_eq!(replayed_steps(&replayed), replay.actions.len());
+        let result = replayed.result.unwrap();
+        assert_eq!(result.multiple, replay.result.multiple);
+        assert_eq!(result.scores, replay.result.scores);
+    }
+}
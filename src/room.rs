@@ -1,31 +1,335 @@
 use crate::{
-    card::{get_sprite_index, new_deck},
-    common::{AddressedEvent, AppState, Event, MyAssets, Socket},
-    lobby::Lobby,
-    player::Player,
+    atlas::PlistAtlas,
+    card::Card,
+    common::{despawn_screen, in_room, AddressedEvent, AppMode, AppState, Event, MyAssets, Socket},
+    game::{Bid, Doubling, Game, GameAction, GameError, Phase, SHOW_HAND_START_MULTIPLE},
+    hand::HandCard,
+    lobby::{update_lobby_tip, Lobby, LobbyTip, LobbyTipText},
+    player::{Player, RoomState},
+    stats::StatsServer,
 };
 use bevy::prelude::*;
 use bevy_matchbox::prelude::*;
 use serde::{Deserialize, Serialize};
 
-type Config = bevy_ggrs::GgrsConfig<u8, PeerId>;
-
-const PLAYER_POSITION: [[f32; 2]; 3] = [[85., 5.], [20., 5.], [20., 85.]];
-const BOTTOM_CARD_POSITION: [[f32; 2]; 1] = [[20., 20.]];
-const LEFT_CARD_POSITION: [[f32; 2]; 1] = [[0., 0.]];
-const RIGHT_CARD_POSITION: [[f32; 2]; 1] = [[0., 0.]];
+// 座位位置 [top, left] 百分比 依次为自己、下家、上家
+const PLAYER_POSITION: [[f32; 2]; 3] = [[68., 2.], [22., 2.], [22., 88.]];
+// 四人时依次为自己、下家、对家、上家
+const FOUR_PLAYER_POSITION: [[f32; 2]; 4] = [[68., 2.], [22., 2.], [2., 75.], [22., 88.]];
+// 对手剩余牌数的位置 与座位位置一一对应 自己的不显示
+const CARD_COUNT_POSITION: [[f32; 2]; 3] = [[0., 0.], [30., 12.], [30., 80.]];
+const FOUR_PLAYER_CARD_COUNT_POSITION: [[f32; 2]; 4] =
+    [[0., 0.], [30., 12.], [8., 82.], [30., 80.]];
+// 最多的座位数
+const MAX_SEATS: usize = 4;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomPlayer {
     pub player: Player,
     pub room_position: i8,
+    pub ready: bool,
+    // 准备时选择了明牌开始
+    pub show_hand: bool,
+}
+
+// 叫地主方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum BidStyle {
+    /**叫分 1-3分 */
+    #[default]
+    Score,
+    /**抢地主 */
+    Rob,
+}
+
+// 玩法 决定座位数和用几副牌
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum GameVariant {
+    /**经典三人一副牌 每人17张 底牌3张 */
+    #[default]
+    Classic,
+    /**四人两副牌 每人25张 底牌8张 */
+    FourPlayer,
+}
+
+impl GameVariant {
+    pub fn seats(&self) -> usize {
+        match self {
+            GameVariant::Classic => 3,
+            GameVariant::FourPlayer => 4,
+        }
+    }
+
+    pub fn decks(&self) -> usize {
+        match self {
+            GameVariant::Classic => 1,
+            GameVariant::FourPlayer => 2,
+        }
+    }
+
+    // 底牌数量
+    pub fn bottom_cards(&self) -> usize {
+        match self {
+            GameVariant::Classic => 3,
+            GameVariant::FourPlayer => 8,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GameVariant::Classic => "三人",
+            GameVariant::FourPlayer => "四人两副",
+        }
+    }
+}
+
+// 场次 决定每分输赢多少金币和进入房间需要的最低金币
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Stake {
+    /**初级房 */
+    #[default]
+    Novice,
+    /**中级房 */
+    Junior,
+    /**高级房 */
+    Senior,
+    /**大师房 */
+    Master,
+}
+
+impl Stake {
+    pub const ALL: [Stake; 4] = [Stake::Novice, Stake::Junior, Stake::Senior, Stake::Master];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stake::Novice => "初级房",
+            Stake::Junior => "中级房",
+            Stake::Senior => "高级房",
+            Stake::Master => "大师房",
+        }
+    }
+
+    pub fn coins_per_point(&self) -> u64 {
+        match self {
+            Stake::Novice => 10,
+            Stake::Junior => 50,
+            Stake::Senior => 200,
+            Stake::Master => 1000,
+        }
+    }
+
+    pub fn min_coins(&self) -> u64 {
+        match self {
+            Stake::Novice => 100,
+            Stake::Junior => 1000,
+            Stake::Senior => 5000,
+            Stake::Master => 20000,
+        }
+    }
+
+    // 金币是否够进入该场次
+    pub fn affordable(&self, player: &Player) -> bool {
+        player.profile.coins >= self.min_coins()
+    }
+}
+
+// 可选的底分
+const BASE_SCORES: [u32; 4] = [1, 2, 5, 10];
+// 可选的封顶倍数 None为不封顶
+const MAX_MULTIPLES: [Option<u32>; 4] = [None, Some(16), Some(32), Some(64)];
+// 可选的每手出牌时间 None为不限时
+const TURN_SECONDS: [Option<u32>; 3] = [None, Some(15), Some(30)];
+
+// 房间规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomRules {
+    pub variant: GameVariant,
+    pub bid_style: BidStyle,
+    // 是否开启记牌器
+    pub card_counter: bool,
+    // 癞子玩法 发牌后翻出一个点数作为癞子
+    pub laizi: bool,
+    // 不洗牌 用上一局收起的牌切一次后几张一发
+    pub no_shuffle: bool,
+    // 底分 结算时乘以倍数
+    pub base_score: u32,
+    // 封顶倍数
+    pub max_multiple: Option<u32>,
+    // 是否允许四带两对
+    pub four_with_two_pairs: bool,
+    // 飞机的翅膀能否带炸弹
+    pub bomb_wings: bool,
+    // 是否允许明牌翻倍
+    pub show_hand: bool,
+    // 每手的思考时间 超时由房主代为操作
+    pub turn_seconds: Option<u32>,
+    // 叫完地主后是否有加倍阶段
+    pub doubling: bool,
+    // 场次
+    pub stake: Stake,
+}
+
+impl Default for RoomRules {
+    fn default() -> Self {
+        Self {
+            variant: GameVariant::default(),
+            bid_style: BidStyle::default(),
+            card_counter: false,
+            laizi: false,
+            no_shuffle: false,
+            base_score: BASE_SCORES[0],
+            max_multiple: None,
+            four_with_two_pairs: true,
+            bomb_wings: true,
+            show_hand: false,
+            turn_seconds: None,
+            doubling: false,
+            stake: Stake::default(),
+        }
+    }
+}
+
+impl BidStyle {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BidStyle::Score => "叫分",
+            BidStyle::Rob => "抢地主",
+        }
+    }
+}
+
+impl RoomRules {
+    // 规则摘要 用于房间列表和房间内展示 开关类的规则只列出与默认不同的
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            self.stake.label().to_string(),
+            self.variant.label().to_string(),
+            self.bid_style.label().to_string(),
+            format!("底分{}", self.base_score),
+        ];
+        if let Some(max) = self.max_multiple {
+            parts.push(format!("{}倍封顶", max));
+        }
+        if !self.four_with_two_pairs {
+            parts.push("禁四带两对".to_string());
+        }
+        if !self.bomb_wings {
+            parts.push("飞机不带炸".to_string());
+        }
+        let flags = [
+            (self.doubling, "加倍"),
+            (self.show_hand, "明牌"),
+            (self.laizi, "癞子"),
+            (self.no_shuffle, "不洗牌"),
+            (self.card_counter, "记牌器"),
+        ];
+        parts.extend(
+            flags
+                .iter()
+                .filter(|(on, _)| *on)
+                .map(|(_, label)| label.to_string()),
+        );
+        if let Some(seconds) = self.turn_seconds {
+            parts.push(format!("每手{}秒", seconds));
+        }
+        parts.join(" ")
+    }
+}
+
+// 选项列表中的下一个 到末尾后回到第一个
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options.iter().position(|option| *option == current);
+    options[index.map_or(0, |index| (index + 1) % options.len())]
+}
+
+// 全员准备后开始游戏的倒计时秒数
+const START_COUNTDOWN: f32 = 3.;
+
+// 房间号位数
+pub const ROOM_CODE_LEN: usize = 6;
+
+// 房间可见性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RoomVisibility {
+    /**公开 显示在房间列表中 */
+    #[default]
+    Public,
+    /**密码房 只能输入房间号和密码加入 */
+    Private,
+    /**邀请房 只能输入房间号加入 */
+    InviteOnly,
+}
+
+impl RoomVisibility {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RoomVisibility::Public => "公开",
+            RoomVisibility::Private => "密码",
+            RoomVisibility::InviteOnly => "邀请",
+        }
+    }
+}
+
+// 加入房间失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinRoomError {
+    NotFound,
+    Full,
+    Gaming,
+    PasswordRequired,
+    WrongPassword,
+    // 金币低于场次的最低要求
+    NotEnoughCoins,
+}
+
+impl std::fmt::Display for JoinRoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinRoomError::NotFound => write!(f, "房间号不存在"),
+            JoinRoomError::Full => write!(f, "房间已满"),
+            JoinRoomError::Gaming => write!(f, "房间正在游戏中"),
+            JoinRoomError::PasswordRequired => write!(f, "请输入房间密码"),
+            JoinRoomError::WrongPassword => write!(f, "房间密码错误"),
+            JoinRoomError::NotEnoughCoins => write!(f, "金币不足 不能进入该场次"),
+        }
+    }
+}
+
+// 房主修改规则失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RulesError {
+    // 已入座的人数超过新玩法的座位数
+    TooManyPlayers,
+    // 有玩家的金币不够新的场次
+    NotEnoughCoins,
+}
+
+impl std::fmt::Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RulesError::TooManyPlayers => write!(f, "房间人数超过该玩法的座位数"),
+            RulesError::NotEnoughCoins => write!(f, "有玩家金币不足该场次"),
+        }
+    }
 }
 
 // 客户端房间资源
 #[derive(Resource, Serialize, Deserialize, Clone, Debug, Component)]
 pub struct Room {
-    pub players: [Option<RoomPlayer>; 3],
+    // 房间号 创建时生成
+    pub code: u32,
+    // 座位 数量由玩法决定
+    pub players: Vec<Option<RoomPlayer>>,
     pub owner: RoomPlayer,
+    pub state: RoomState,
+    pub rules: RoomRules,
+    pub visibility: RoomVisibility,
+    // 密码房的密码 只同步给房间内的玩家
+    pub password: Option<String>,
+    // 全员准备后房主点了开始 正在倒计时
+    pub starting: bool,
+    // 进行中的一局
+    pub game: Option<Game>,
     // 房间数据是否有变化
     pub changed: bool,
 }
@@ -33,100 +337,619 @@ pub struct Room {
 #[derive(Component)]
 pub struct RoomUIComponent;
 
-// #[derive(Component)]
-// pub struct DealCardTimer(pub Timer);
+// 房间号文字
+#[derive(Component)]
+pub struct RoomCodeText;
+
+// 房间成员列表 随房间变化重建
+#[derive(Component)]
+pub struct RoomMemberList;
+
+// 准备、开始和叫地主按钮所在区域 随房间变化重建
+#[derive(Component)]
+pub struct RoomActionBar;
+
+// 房间规则 房主在等待时可以修改
+#[derive(Component)]
+pub struct RoomRulesBar;
+
+// 开始游戏倒计时文字
+#[derive(Component)]
+pub struct CountdownText;
+
+// 座位 数值为相对自己的位置 0自己 1下家 2上家 四人时2为对家 3为上家
+#[derive(Component)]
+pub struct SeatView(pub usize);
+
+// 对手剩余牌数 数值与SeatView一致
+#[derive(Component)]
+pub struct SeatCardCount(pub usize);
+
+#[derive(Component)]
+pub enum RoomButton {
+    Leave,
+    Kick(PeerId),
+    // 准备或取消准备
+    Ready(bool),
+    // 明牌开始 准备并在发牌后明牌
+    ShowHandStart,
+    // 叫地主阶段明牌
+    ShowHand,
+    Start,
+    // 房主修改房间规则
+    Rules(RoomRules),
+    Bid(Bid),
+    Double(Doubling),
+    // 打出选中的牌
+    Play,
+    Pass,
+}
+
+// 开始游戏倒计时 每个客户端根据房间的starting各自计时
+#[derive(Resource, Default)]
+pub struct StartCountdown(pub Option<Timer>);
+
+// 出牌倒计时 轮到的人或阶段变化时重新计时 超时由房主代为操作
+#[derive(Resource, Default)]
+pub struct TurnCountdown(pub Option<(TurnKey, Timer)>);
+
+// 用于判断是否轮到了新的一次操作 依次为阶段、座位、叫地主次数、出牌次数
+type TurnKey = (Phase, usize, usize, usize);
+
+// 出牌倒计时文字
+#[derive(Component)]
+pub struct TurnCountdownText;
 
 impl Room {
-    pub fn new(player: Player) -> Self {
+    pub fn new(player: Player, code: u32) -> Self {
         let rome_player = RoomPlayer {
             player,
             room_position: 0,
+            ready: false,
+            show_hand: false,
         };
+        let rules = RoomRules::default();
+        let mut players = vec![None; rules.variant.seats()];
+        players[0] = Some(rome_player.clone());
         Self {
-            players: [Some(rome_player.clone()), None, None],
+            code,
+            players,
             owner: rome_player,
+            state: RoomState::Waiting,
+            rules,
+            visibility: RoomVisibility::Public,
+            password: None,
+            starting: false,
+            game: None,
             changed: true,
         }
     }
 
-    pub fn join(&mut self, player: Player) -> bool {
-        // 找到空位 并分配空位
-        if let Some(index) = self.players.iter().position(|v| v.is_none()) {
-            self.players[index] = Some(RoomPlayer {
-                player,
-                room_position: index as i8,
-            });
-            true
+    pub fn with_visibility(mut self, visibility: RoomVisibility, password: Option<String>) -> Self {
+        self.visibility = visibility;
+        self.password = password.filter(|_| visibility == RoomVisibility::Private);
+        self
+    }
+
+    // 发给房间外玩家的房间信息 不包含密码和牌局
+    pub fn public_view(&self) -> Self {
+        Self {
+            password: None,
+            game: None,
+            ..self.clone()
+        }
+    }
+
+    // 发给房间成员的房间信息 只能看到自己和明牌的手牌
+    pub fn member_view(&self, peer: PeerId) -> Self {
+        let seat = self.seat_of(peer);
+        Self {
+            game: self.game.as_ref().map(|game| game.masked(seat)),
+            ..self.clone()
+        }
+    }
+
+    // 玩家所在的座位
+    pub fn seat_of(&self, peer: PeerId) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|p| p.as_ref().is_some_and(|p| p.player.id == peer))
+    }
+
+    // 座位相对自己的位置 0自己 1下家 最后一个是上家
+    pub fn relative_seat(&self, seat: usize, local: PeerId) -> usize {
+        let len = self.players.len();
+        let own = self
+            .seat_of(local)
+            .and_then(|index| self.players[index].as_ref())
+            .map(|p| p.room_position as usize)
+            .unwrap_or_default();
+        (seat + len - own) % len
+    }
+
+    // 坐满且全员准备
+    pub fn all_ready(&self) -> bool {
+        self.is_full() && self.players.iter().flatten().all(|p| p.ready)
+    }
+
+    // 明牌开始需要房间规则允许 取消准备时一起取消 金币不够场次时不能准备
+    pub fn set_ready(&mut self, peer: PeerId, ready: bool, show_hand: bool) {
+        let show_hand = ready && show_hand && self.rules.show_hand;
+        let stake = self.rules.stake;
+        if let Some(room_player) = self
+            .players
+            .iter_mut()
+            .flatten()
+            .find(|p| p.player.id == peer)
+            .filter(|p| !ready || stake.affordable(&p.player))
+        {
+            room_player.ready = ready;
+            room_player.show_hand = show_hand;
+            self.changed = true;
+        }
+    }
+
+    // 倒计时结束 开始一局 由房主的座位先叫地主
+    // 不洗牌时收起上一局的牌接着发 上一局中止或是第一局则正常洗牌
+    pub fn start_game(&mut self) {
+        let first = self.seat_of(self.owner.player.id).unwrap_or_default();
+        let stack = match &self.game {
+            Some(game) if self.rules.no_shuffle && game.phase == Phase::Finished => game.gathered(),
+            _ => vec![],
+        };
+        let mut game = Game::with_stack(self.rules.clone(), first, stack);
+        for (seat, room_player) in self.players.iter().enumerate() {
+            if room_player.as_ref().is_some_and(|p| p.show_hand) {
+                let _ = game.show_hand(seat, SHOW_HAND_START_MULTIPLE);
+            }
+        }
+        self.game = Some(game);
+        self.state = RoomState::Gaming;
+        self.starting = false;
+        self.changed = true;
+    }
+
+    // 修改规则 玩法的座位数变化时按原来的顺序重新入座
+    // 已入座的人数超过新的座位数或有人金币不够新的场次时不能修改
+    pub fn set_rules(&mut self, rules: RoomRules) -> Result<(), RulesError> {
+        let seats = rules.variant.seats();
+        if self.seated() > seats {
+            return Err(RulesError::TooManyPlayers);
+        }
+        if self
+            .players
+            .iter()
+            .flatten()
+            .any(|p| !rules.stake.affordable(&p.player))
+        {
+            return Err(RulesError::NotEnoughCoins);
+        }
+        if seats != self.players.len() {
+            let mut players: Vec<Option<RoomPlayer>> =
+                self.players.drain(..).flatten().map(Some).collect();
+            players.resize(seats, None);
+            for (index, room_player) in players.iter_mut().enumerate() {
+                if let Some(room_player) = room_player {
+                    room_player.room_position = index as i8;
+                }
+            }
+            self.players = players;
+        }
+        self.rules = rules;
+        self.changed = true;
+        Ok(())
+    }
+
+    // 没有进行中的一局 可以准备开始
+    pub fn waiting(&self) -> bool {
+        self.game
+            .as_ref()
+            .is_none_or(|game| game.phase == Phase::Finished)
+    }
+
+    // 执行座位上玩家的操作 出完牌后回到等待状态 保留结算结果
+    pub fn apply_action(&mut self, peer: PeerId, action: &GameAction) -> Result<(), GameError> {
+        let seat = self.seat_of(peer).ok_or(GameError::NotYourTurn)?;
+        self.apply_seat_action(seat, action)
+    }
+
+    // 执行座位的操作 出牌超时时房主也用它代为操作
+    pub fn apply_seat_action(&mut self, seat: usize, action: &GameAction) -> Result<(), GameError> {
+        let game = self.game.as_mut().ok_or(GameError::WrongPhase)?;
+        game.apply(seat, action)?;
+        if game.phase == Phase::Finished {
+            self.settle_coins();
+            self.state = RoomState::Waiting;
+            for room_player in self.players.iter_mut().flatten() {
+                room_player.ready = false;
+                room_player.show_hand = false;
+            }
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    // 按结算的分数在地主和每个农民之间转移金币 输的一方最多输光身上的金币
+    fn settle_coins(&mut self) {
+        let Some(game) = self.game.as_mut() else {
+            return;
+        };
+        let (Some(landlord), Some(result)) = (game.landlord, game.result.as_mut()) else {
+            return;
+        };
+        let per_point = self.rules.stake.coins_per_point();
+        let mut balances: Vec<u64> = self
+            .players
+            .iter()
+            .map(|p| p.as_ref().map_or(0, |p| p.player.profile.coins))
+            .collect();
+        for farmer in (0..balances.len()).filter(|seat| *seat != landlord) {
+            let score = result.scores[farmer];
+            let (payer, payee) = if score < 0 {
+                (farmer, landlord)
+            } else {
+                (landlord, farmer)
+            };
+            let amount = (score.unsigned_abs() as u64 * per_point).min(balances[payer]);
+            balances[payer] -= amount;
+            balances[payee] += amount;
+            result.coins[payer] -= amount as i64;
+            result.coins[payee] += amount as i64;
+        }
+        for (room_player, coins) in self.players.iter_mut().zip(balances) {
+            if let Some(room_player) = room_player {
+                room_player.player.profile.coins = coins;
+            }
+        }
+    }
+
+    // 一局结束或中止 回到等待状态 所有人需要重新准备
+    pub fn end_game(&mut self) {
+        self.game = None;
+        self.state = RoomState::Waiting;
+        self.starting = false;
+        for room_player in self.players.iter_mut().flatten() {
+            room_player.ready = false;
+            room_player.show_hand = false;
+        }
+        self.changed = true;
+    }
+
+    pub fn is_member(&self, peer: PeerId) -> bool {
+        self.players.iter().flatten().any(|p| p.player.id == peer)
+    }
+
+    // 已入座人数
+    pub fn seated(&self) -> usize {
+        self.players.iter().filter(|p| p.is_some()).count()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.seated() == self.players.len()
+    }
+
+    // 是否还能加入 满员或者游戏中的房间不能加入
+    pub fn check_join(&self, player: &Player) -> Result<(), JoinRoomError> {
+        if self.state == RoomState::Gaming {
+            Err(JoinRoomError::Gaming)
+        } else if self.is_full() {
+            Err(JoinRoomError::Full)
+        } else if !self.rules.stake.affordable(player) {
+            Err(JoinRoomError::NotEnoughCoins)
         } else {
-            false
+            Ok(())
+        }
+    }
+
+    // 校验密码 只有密码房需要
+    pub fn check_password(&self, password: Option<&str>) -> Result<(), JoinRoomError> {
+        if self.visibility != RoomVisibility::Private {
+            return Ok(());
+        }
+        match password {
+            None => Err(JoinRoomError::PasswordRequired),
+            Some(password) if Some(password) == self.password.as_deref() => Ok(()),
+            Some(_) => Err(JoinRoomError::WrongPassword),
+        }
+    }
+
+    // 加入房间 player中带有加入者的个人资料 随房间同步给其他座位显示昵称和头像
+    pub fn join(&mut self, player: Player, password: Option<&str>) -> Result<(), JoinRoomError> {
+        self.check_join(&player)?;
+        self.check_password(password)?;
+        // 找到空位 并分配空位
+        let index = self
+            .players
+            .iter()
+            .position(|v| v.is_none())
+            .ok_or(JoinRoomError::Full)?;
+        self.players[index] = Some(RoomPlayer {
+            player,
+            room_position: index as i8,
+            ready: false,
+            show_hand: false,
+        });
+        self.changed = true;
+        Ok(())
+    }
+
+    pub fn is_owner(&self, peer: PeerId) -> bool {
+        self.owner.player.id == peer
+    }
+
+    // 玩家离开房间 房主离开时按座位顺序转让给下一位玩家
+    // 返回房间里是否还有玩家 没有则房间解散
+    pub fn leave(&mut self, peer: PeerId) -> bool {
+        let Some(index) = self.seat_of(peer) else {
+            return self.seated() > 0;
+        };
+        self.players[index] = None;
+        // 游戏中有人离开 中止这一局 已经结束的保留结算和不洗牌要收起的牌
+        if self
+            .game
+            .as_ref()
+            .is_some_and(|game| game.phase != Phase::Finished)
+        {
+            self.end_game();
+        }
+        if self.is_owner(peer) {
+            let len = self.players.len();
+            if let Some(next) = (1..len)
+                .filter_map(|offset| self.players[(index + offset) % len].as_ref())
+                .next()
+            {
+                self.owner = next.clone();
+            }
+        }
+        self.changed = true;
+        self.seated() > 0
+    }
+
+    // 跟随房主同步房间 只接受当前房主发来的同步 转让房主时由原房主发来新房主
+    // 返回false表示同步后自己已经不在房间里
+    pub fn sync(&mut self, src: PeerId, synced: &Room, local: PeerId) -> bool {
+        if *synced != *self || src != self.owner.player.id || src == local {
+            return true;
         }
+        if !synced.is_member(local) {
+            return false;
+        }
+        *self = synced.clone();
+        self.changed = false;
+        true
     }
 }
 
 impl PartialEq for Room {
     fn eq(&self, other: &Self) -> bool {
-        self.owner.player == other.owner.player
+        self.code == other.code
     }
 }
 
 impl Eq for Room {}
 
-// const PLAYER_LOCATION: Vec = [];
-
 impl Plugin for RoomUIComponent {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::InRoom), setup)
+        // 房间界面在进入房间时创建 回到大厅时销毁 开局和结束不重建
+        app.init_resource::<StartCountdown>()
+            .init_resource::<TurnCountdown>()
+            .add_systems(
+                OnTransition {
+                    from: AppState::Lobby,
+                    to: AppState::InRoom,
+                },
+                setup.run_if(resource_equals(AppMode::Windowed)),
+            )
+            .add_systems(
+                Update,
+                (
+                    update,
+                    receive_events,
+                    button_system,
+                    start_countdown,
+                    turn_countdown,
+                    sync_coins,
+                    sync_app_state,
+                )
+                    .run_if(in_room),
+            )
             .add_systems(
                 Update,
-                (update, receive_events).run_if(in_state(AppState::InRoom)),
+                (
+                    refresh_members,
+                    refresh_seats,
+                    refresh_actions,
+                    refresh_rules,
+                    update_countdown_text,
+                    update_turn_countdown_text,
+                    update_lobby_tip,
+                )
+                    .run_if(in_room)
+                    .run_if(resource_equals(AppMode::Windowed)),
             )
-            .add_systems(OnEnter(AppState::InRoom), init_card)
-            .add_systems(Update, deal_card.run_if(in_state(AppState::DealCard)));
-        // .add_systems(OnExit(AppState::Playing), despawn_screen::<RoomUIComponent>);
+            .add_systems(OnEnter(AppState::Lobby), despawn_screen::<RoomUIComponent>);
     }
 }
 
-fn init_card(
-    mut commands: Commands,
-    assets: Res<MyAssets>,
-    room: Res<Room>,
-    mut state: ResMut<NextState<AppState>>,
-) {
-    let deck = new_deck();
-    for card in deck {
-        let sprite_index = get_sprite_index(&card);
-        commands
-            .spawn(SpriteSheetBundle {
-                texture_atlas: assets.card.to_owned(),
-                transform: Transform::from_xyz(0., 0.0, 0.0),
-                sprite: TextureAtlasSprite::new(sprite_index),
+pub fn setup(mut commands: Commands, assets: Res<MyAssets>, room: Res<Room>) {
+    commands.spawn((
+        TextBundle::from_section(
+            format!("房间号: {} ({})", room.code, room.visibility.label()),
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: 28.,
+                color: Color::GOLD,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(2.),
+            left: Val::Percent(2.),
+            ..Default::default()
+        }),
+        RoomCodeText,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(2.),
+                left: Val::Percent(30.),
+                right: Val::Percent(2.),
+                align_items: AlignItems::Center,
+                flex_wrap: FlexWrap::Wrap,
+                column_gap: Val::Px(8.),
+                row_gap: Val::Px(4.),
                 ..Default::default()
-            })
-            .insert(card);
+            },
+            ..Default::default()
+        },
+        RoomRulesBar,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        ButtonBundle {
+            image: assets.btn_return_room.clone().into(),
+            style: Style {
+                width: Val::Percent(12.),
+                height: Val::Percent(9.),
+                position_type: PositionType::Absolute,
+                top: Val::Percent(2.),
+                right: Val::Percent(2.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        RoomButton::Leave,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Percent(13.),
+                right: Val::Percent(2.),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        RoomMemberList,
+        RoomUIComponent,
+    ));
+    // 座位和剩余牌数按最多的座位数创建 位置随玩法变化
+    for index in 0..MAX_SEATS {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(4.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            SeatView(index),
+            RoomUIComponent,
+        ));
+    }
+    for index in 1..MAX_SEATS {
+        commands.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: assets.font.clone(),
+                    font_size: 32.,
+                    color: Color::GOLD,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            }),
+            SeatCardCount(index),
+            RoomUIComponent,
+        ));
     }
-}
-
-fn deal_card(
-    mut commands: Commands,
-    // mut deck: Vec<Card>,
-    // card_textures: Res<CardTextureAtlas>,
-    // mut players: ResMut<Vec<Player>>,
-) {
-    // println!("{:?}", time.delta_seconds());
-    // *card_deal_timer += time.delta_seconds();
-    // let mut index = 1.;
-    // for (mut transfrom, card) in &mut q_card {
-    //     if *card_deal_timer > 0.1 {
-    //         transfrom.translation.x = transfrom.translation.x + 80. * time.delta_seconds();
-    //         transfrom.translation.y = transfrom.translation.y - 80. * time.delta_seconds();
-    //         index = index + 1.;
-    //         // println!("{:?}", card);
-    //         *card_deal_timer = 0.0;
-    //     }
-    // }
-}
-
-pub fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Percent(30.),
+                width: Val::Percent(100.),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(16.),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        RoomActionBar,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: 24.,
+                color: Color::GOLD,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Percent(38.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        })
+        .with_text_alignment(TextAlignment::Center),
+        TurnCountdownText,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: 40.,
+                color: Color::GOLD,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(40.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        })
+        .with_text_alignment(TextAlignment::Center),
+        CountdownText,
+        RoomUIComponent,
+    ));
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: 30.,
+                color: Color::ORANGE_RED,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(52.),
+            width: Val::Percent(100.),
+            justify_content: JustifyContent::Center,
+            ..Default::default()
+        })
+        .with_text_alignment(TextAlignment::Center),
+        LobbyTipText,
+        RoomUIComponent,
+    ));
     commands.spawn((
         SpriteBundle {
             texture: assets.table_bg_1.clone(),
@@ -140,149 +963,1141 @@ pub fn setup(mut commands: Commands, assets: Res<MyAssets>) {
     ));
 }
 
+// 发布房间信息 房间内成员各自收到只含自己可见手牌的信息 其他客户端收到不含密码和牌局的信息
+// 成员走可靠通道 保证明牌等状态一定同步到
+fn broadcast_room(socket: &mut Socket, local: &Player, room: &Room) {
+    let (members, others): (Vec<PeerId>, Vec<PeerId>) = socket
+        .connected_peers()
+        .into_iter()
+        .partition(|peer| room.is_member(*peer));
+    for peer in members {
+        socket.send_reliable(
+            AddressedEvent {
+                src: local.clone(),
+                event: Event::SyncRoom(room.member_view(peer)),
+            },
+            vec![peer],
+        );
+    }
+    socket.send_unreliable(
+        AddressedEvent {
+            src: local.clone(),
+            event: Event::SyncRoom(room.public_view()),
+        },
+        others,
+    );
+}
+
+// 离开房间 房主离开时转让房主 最后一人离开时解散房间
+// 离开和解散的消息走可靠通道 丢失后其他客户端会停留在过期的房间里
+fn leave_room(
+    commands: &mut Commands,
+    room: &mut Room,
+    local: &Player,
+    socket: &mut Socket,
+    lobby: &mut Lobby,
+    state: &mut NextState<AppState>,
+) {
+    if room.is_owner(local.id) {
+        if room.leave(local.id) {
+            broadcast_room(socket, local, room);
+        } else {
+            let peers = socket.connected_peers();
+            socket.send_reliable(
+                AddressedEvent {
+                    src: local.clone(),
+                    event: Event::DissolveRoom(room.code),
+                },
+                peers,
+            );
+            lobby.remove_room(room.code);
+        }
+    } else {
+        socket.send_reliable(
+            AddressedEvent {
+                src: local.clone(),
+                event: Event::LeaveRoom(room.code),
+            },
+            vec![room.owner.player.id],
+        );
+    }
+    commands.remove_resource::<Room>();
+    state.set(AppState::Lobby);
+}
+
+// 发送准备状态 房主直接修改 其他玩家发给房主 明牌开始走可靠通道
+fn send_ready(room: &mut Room, local: &Player, socket: &mut Socket, ready: bool, show_hand: bool) {
+    if room.is_owner(local.id) {
+        room.set_ready(local.id, ready, show_hand);
+        return;
+    }
+    let event = AddressedEvent {
+        src: local.clone(),
+        event: Event::Ready { ready, show_hand },
+    };
+    let peers = vec![room.owner.player.id];
+    if show_hand {
+        socket.send_reliable(event, peers);
+    } else {
+        socket.send_unreliable(event, peers);
+    }
+}
+
+// 发送牌局操作 房主直接执行 其他玩家发给房主校验
+fn send_action(room: &mut Room, local: &Player, socket: &mut Socket, action: GameAction) {
+    if room.is_owner(local.id) {
+        apply_action(room, local.id, &action);
+        return;
+    }
+    let reliable = matches!(action, GameAction::ShowHand);
+    let event = AddressedEvent {
+        src: local.clone(),
+        event: Event::GameAction(action),
+    };
+    let peers = vec![room.owner.player.id];
+    // 明牌必须送达
+    if reliable {
+        socket.send_reliable(event, peers);
+    } else {
+        socket.send_unreliable(event, peers);
+    }
+}
+
+// 房主执行玩家的牌局操作 无效操作直接忽略
+fn apply_action(room: &mut Room, peer: PeerId, action: &GameAction) {
+    if let Err(err) = room.apply_action(peer, action) {
+        warn!("{:?} 的操作 {:?} 无效: {}", peer, action, err);
+    }
+}
+
 fn update(
-    mut commands: Commands,
     local: Res<Player>,
-    assets: Res<MyAssets>,
     mut room: ResMut<Room>,
-    mut state: ResMut<NextState<AppState>>,
     mut socket: ResMut<Socket>,
+    mut lobby: ResMut<Lobby>,
 ) {
-    // 实时发布房间信息
+    for (peer, state) in socket.peer_changes() {
+        match state {
+            // 有新的客户端连入时 重新发布房间信息 保证大厅房间列表完整
+            PeerState::Connected => room.changed = true,
+            // 断开连接视为离开房间 每个成员都按相同规则转让房主
+            // 大厅不在运行 由这里移除断开的房主的房间
+            PeerState::Disconnected => {
+                lobby.remove_room_by_peer(peer);
+                if room.is_member(peer) {
+                    room.leave(peer);
+                }
+            }
+        }
+    }
+    // 实时发布房间信息 由房主负责
+    if room.changed && room.is_owner(local.id) {
+        broadcast_room(&mut socket, &local, &room);
+    }
     if room.changed {
-        let peers = socket
-            .unreliable_connected_peers()
-            .collect::<Vec<PeerId>>()
-            .to_owned();
-        socket.send_unreliable(
-            AddressedEvent {
-                src: local.clone(),
-                event: Event::SyncRoom(room.clone()),
+        room.changed = false;
+    }
+}
+
+fn button_system(
+    query: Query<(&Interaction, &RoomButton), (Changed<Interaction>, With<Button>)>,
+    hand: Query<(&Card, &HandCard)>,
+    mut tip: ResMut<LobbyTip>,
+    mut commands: Commands,
+    mut room: ResMut<Room>,
+    mut socket: ResMut<Socket>,
+    mut lobby: ResMut<Lobby>,
+    mut state: ResMut<NextState<AppState>>,
+    local: Res<Player>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            RoomButton::Leave => leave_room(
+                &mut commands,
+                &mut room,
+                &local,
+                &mut socket,
+                &mut lobby,
+                &mut state,
+            ),
+            // 只有房主可以踢人
+            RoomButton::Kick(peer) if room.is_owner(local.id) && *peer != local.id => {
+                room.leave(*peer);
+                socket.send_reliable(
+                    AddressedEvent {
+                        src: local.clone(),
+                        event: Event::Kicked(room.code),
+                    },
+                    vec![*peer],
+                );
+            }
+            RoomButton::Kick(_) => {}
+            // 金币不够场次时不能准备
+            RoomButton::Ready(true) | RoomButton::ShowHandStart
+                if !room.rules.stake.affordable(&local) =>
+            {
+                tip.show(JoinRoomError::NotEnoughCoins.to_string())
+            }
+            RoomButton::Ready(ready) => send_ready(&mut room, &local, &mut socket, *ready, false),
+            RoomButton::ShowHandStart => send_ready(&mut room, &local, &mut socket, true, true),
+            RoomButton::ShowHand => {
+                send_action(&mut room, &local, &mut socket, GameAction::ShowHand)
+            }
+            // 只有房主可以开始 并且需要全员准备
+            RoomButton::Start if room.is_owner(local.id) && room.all_ready() => {
+                room.starting = true;
+                room.changed = true;
+            }
+            RoomButton::Start => {}
+            // 开局倒计时和牌局中不能修改规则
+            RoomButton::Rules(rules)
+                if room.is_owner(local.id) && room.waiting() && !room.starting =>
+            {
+                if let Err(err) = room.set_rules(rules.clone()) {
+                    tip.show(err.to_string());
+                }
+            }
+            RoomButton::Rules(_) => {}
+            RoomButton::Bid(bid) => {
+                send_action(&mut room, &local, &mut socket, GameAction::Bid(*bid))
+            }
+            RoomButton::Double(double) => {
+                send_action(&mut room, &local, &mut socket, GameAction::Double(*double))
+            }
+            RoomButton::Play => {
+                let mut cards: Vec<(usize, Card)> = hand
+                    .iter()
+                    .filter(|(_, hand_card)| hand_card.selected)
+                    .map(|(card, hand_card)| (hand_card.index, *card))
+                    .collect();
+                cards.sort_by_key(|(index, _)| *index);
+                let cards: Vec<Card> = cards.into_iter().map(|(_, card)| card).collect();
+                // 先在本地校验 避免把无效的牌发给房主
+                let checked = match (room.seat_of(local.id), &room.game) {
+                    (Some(seat), Some(game)) => game.check_play(seat, &cards).map(|_| ()),
+                    _ => Err(GameError::WrongPhase),
+                };
+                match checked {
+                    Ok(()) => send_action(&mut room, &local, &mut socket, GameAction::Play(cards)),
+                    Err(err) => tip.show(err.to_string()),
+                }
+            }
+            RoomButton::Pass => send_action(&mut room, &local, &mut socket, GameAction::Pass),
+        }
+    }
+}
+
+// 开始倒计时 结束时由房主发牌开局 倒计时中有人取消准备或离开则中止
+fn start_countdown(
+    time: Res<Time>,
+    mut countdown: ResMut<StartCountdown>,
+    mut room: ResMut<Room>,
+    local: Res<Player>,
+) {
+    let owner = room.is_owner(local.id);
+    if room.starting && owner && !room.all_ready() {
+        room.starting = false;
+        room.changed = true;
+    }
+    if !room.starting {
+        countdown.0 = None;
+        return;
+    }
+    let timer = countdown
+        .0
+        .get_or_insert_with(|| Timer::from_seconds(START_COUNTDOWN, TimerMode::Once));
+    if timer.tick(time.delta()).finished() && owner {
+        room.start_game();
+    }
+}
+
+// 开启出牌时间时 每次轮到新的操作都重新计时 超时后房主替轮到的座位操作
+fn turn_countdown(
+    time: Res<Time>,
+    mut countdown: ResMut<TurnCountdown>,
+    mut room: ResMut<Room>,
+    local: Res<Player>,
+) {
+    let turn = room.game.as_ref().and_then(|game| {
+        let seconds = room.rules.turn_seconds?;
+        let key = (game.phase, game.turn, game.bids.len(), game.plays.len());
+        (game.phase != Phase::Finished).then_some((key, seconds))
+    });
+    let Some((key, seconds)) = turn else {
+        countdown.0 = None;
+        return;
+    };
+    if countdown.0.as_ref().map(|(k, _)| *k) != Some(key) {
+        countdown.0 = Some((key, Timer::from_seconds(seconds as f32, TimerMode::Once)));
+    }
+    let Some((_, timer)) = countdown.0.as_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).just_finished() || !room.is_owner(local.id) {
+        return;
+    }
+    let Some(game) = room.game.as_ref() else {
+        return;
+    };
+    let (seat, action) = (game.turn, game.timeout_action());
+    if let Err(err) = room.apply_seat_action(seat, &action) {
+        warn!("座位 {} 超时操作 {:?} 无效: {}", seat, action, err);
+    }
+}
+
+// 结算后房主改了房间里的金币 同步到自己的个人资料并保存
+fn sync_coins(room: Res<Room>, mut local: ResMut<Player>) {
+    if !room.is_changed() {
+        return;
+    }
+    let Some(coins) = room
+        .seat_of(local.id)
+        .and_then(|seat| room.players[seat].as_ref())
+        .map(|p| p.player.profile.coins)
+    else {
+        return;
+    };
+    if coins != local.profile.coins {
+        local.profile.coins = coins;
+        local.profile.save();
+    }
+}
+
+// 根据房间里的牌局切换游戏状态
+fn sync_app_state(
+    room: Res<Room>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // 同一帧已经要切换到其他状态 比如离开房间
+    if next_state.0.is_some() {
+        return;
+    }
+    let target = match &room.game {
+        None => AppState::InRoom,
+        Some(game) if game.phase == Phase::Finished => AppState::GameOver,
+        Some(_) => AppState::Playing,
+    };
+    if *state.get() != target {
+        next_state.set(target);
+    }
+}
+
+fn update_countdown_text(
+    countdown: Res<StartCountdown>,
+    mut query: Query<&mut Text, With<CountdownText>>,
+) {
+    let value = match &countdown.0 {
+        Some(timer) => format!("{} 秒后开始", timer.remaining_secs().ceil() as u32),
+        None => String::new(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// 轮到的玩家和剩余秒数
+fn update_turn_countdown_text(
+    countdown: Res<TurnCountdown>,
+    room: Res<Room>,
+    mut query: Query<&mut Text, With<TurnCountdownText>>,
+) {
+    let value = match &countdown.0 {
+        Some(((_, seat, _, _), timer)) => {
+            let name = room.players[*seat]
+                .as_ref()
+                .map(|p| p.player.name())
+                .unwrap_or_default();
+            format!("{} {}秒", name, timer.remaining_secs().ceil() as u32)
+        }
+        None => String::new(),
+    };
+    for mut text in query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+// 操作区域 等待时显示准备和开始 叫地主时显示轮到自己的选项
+fn refresh_actions(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    room: Res<Room>,
+    local: Res<Player>,
+    bar: Query<Entity, With<RoomActionBar>>,
+    added: Query<(), Added<RoomActionBar>>,
+) {
+    if !room.is_changed() && added.is_empty() {
+        return;
+    }
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 28.,
+        color: Color::WHITE,
+    };
+    let image_style = Style {
+        width: Val::Px(160.),
+        height: Val::Px(64.),
+        ..Default::default()
+    };
+    let seat = room.seat_of(local.id);
+    for entity in bar.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands
+            .entity(entity)
+            .with_children(|parent| match &room.game {
+                _ if room.waiting() => {
+                    let ready = seat
+                        .and_then(|seat| room.players[seat].as_ref())
+                        .is_some_and(|p| p.ready);
+                    // 已准备时再点一次取消准备
+                    let image = if ready {
+                        assets.gray_ready.clone()
+                    } else {
+                        assets.btn_ready.clone()
+                    };
+                    parent.spawn((
+                        ButtonBundle {
+                            image: image.into(),
+                            style: image_style.clone(),
+                            ..Default::default()
+                        },
+                        RoomButton::Ready(!ready),
+                    ));
+                    if room.rules.show_hand && !ready {
+                        spawn_text_button(
+                            parent,
+                            "明牌开始",
+                            text_style.clone(),
+                            RoomButton::ShowHandStart,
+                        );
+                    }
+                    if room.is_owner(local.id) {
+                        // 没有全员准备时开始按钮置灰
+                        let color = if room.all_ready() && !room.starting {
+                            Color::WHITE
+                        } else {
+                            Color::GRAY
+                        };
+                        parent.spawn((
+                            ButtonBundle {
+                                image: assets.btn_start.clone().into(),
+                                style: image_style.clone(),
+                                background_color: color.into(),
+                                ..Default::default()
+                            },
+                            RoomButton::Start,
+                        ));
+                    }
+                }
+                Some(game) if game.phase == Phase::Bidding => {
+                    // 叫地主阶段任何时候都可以明牌
+                    if game.rules.show_hand && seat.is_some_and(|seat| game.shown[seat].is_none()) {
+                        spawn_text_button(parent, "明牌", text_style.clone(), RoomButton::ShowHand);
+                    }
+                    if Some(game.turn) != seat {
+                        return;
+                    }
+                    for bid in game.bid_options() {
+                        // 抢地主阶段使用图片按钮
+                        let image = match bid {
+                            Bid::Rob if game.called() => Some(assets.btn_rob.clone()),
+                            Bid::Pass if game.called() => Some(assets.btn_no_rob.clone()),
+                            _ => None,
+                        };
+                        match image {
+                            Some(image) => {
+                                parent.spawn((
+                                    ButtonBundle {
+                                        image: image.into(),
+                                        style: image_style.clone(),
+                                        ..Default::default()
+                                    },
+                                    RoomButton::Bid(bid),
+                                ));
+                            }
+                            None => spawn_text_button(
+                                parent,
+                                &bid.label(game.called()),
+                                text_style.clone(),
+                                RoomButton::Bid(bid),
+                            ),
+                        }
+                    }
+                }
+                Some(game) if game.phase == Phase::Doubling && Some(game.turn) == seat => {
+                    for double in Doubling::ALL {
+                        spawn_text_button(
+                            parent,
+                            double.label(),
+                            text_style.clone(),
+                            RoomButton::Double(double),
+                        );
+                    }
+                }
+                Some(game) if game.phase == Phase::Playing && Some(game.turn) == seat => {
+                    // 首家不能不出
+                    if game.following() {
+                        parent.spawn((
+                            ButtonBundle {
+                                image: assets.btn_pass.clone().into(),
+                                style: image_style.clone(),
+                                ..Default::default()
+                            },
+                            RoomButton::Pass,
+                        ));
+                    }
+                    parent.spawn((
+                        ButtonBundle {
+                            image: assets.btn_play.clone().into(),
+                            style: image_style.clone(),
+                            ..Default::default()
+                        },
+                        RoomButton::Play,
+                    ));
+                }
+                _ => {}
+            });
+    }
+}
+
+// 操作区域中没有图片的文字按钮
+fn spawn_text_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    text_style: TextStyle,
+    button: RoomButton,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(20.), Val::Px(8.)),
+                    ..Default::default()
+                },
+                background_color: Color::ORANGE.into(),
+                ..Default::default()
             },
-            peers,
-        );
+            button,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, text_style));
+        });
+}
+
+// 房间规则 房主等待时显示切换按钮 其他情况只显示摘要
+fn refresh_rules(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    room: Res<Room>,
+    local: Res<Player>,
+    bar: Query<Entity, With<RoomRulesBar>>,
+    added: Query<(), Added<RoomRulesBar>>,
+) {
+    if !room.is_changed() && added.is_empty() {
+        return;
     }
-    // 更新房间房主 默认数组第一个
-    if let Some(first) = &room.players[0] {
-        room.owner = first.to_owned();
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    let editable = room.is_owner(local.id) && room.waiting() && !room.starting;
+    let rules = &room.rules;
+    for entity in bar.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "规则:",
+                TextStyle {
+                    color: Color::GOLD,
+                    ..text_style.clone()
+                },
+            ));
+            if !editable {
+                parent.spawn(TextBundle::from_section(
+                    rules.summary(),
+                    text_style.clone(),
+                ));
+                return;
+            }
+            let bid_style = match rules.bid_style {
+                BidStyle::Score => BidStyle::Rob,
+                BidStyle::Rob => BidStyle::Score,
+            };
+            let variant = match rules.variant {
+                GameVariant::Classic => GameVariant::FourPlayer,
+                GameVariant::FourPlayer => GameVariant::Classic,
+            };
+            let options = [
+                (
+                    rules.stake.label().to_string(),
+                    RoomRules {
+                        stake: cycle(&Stake::ALL, rules.stake),
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    rules.variant.label().to_string(),
+                    RoomRules {
+                        variant,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    rules.bid_style.label().to_string(),
+                    RoomRules {
+                        bid_style,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("底分: {}", rules.base_score),
+                    RoomRules {
+                        base_score: cycle(&BASE_SCORES, rules.base_score),
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    match rules.max_multiple {
+                        Some(max) => format!("封顶: {}倍", max),
+                        None => "封顶: 无".to_string(),
+                    },
+                    RoomRules {
+                        max_multiple: cycle(&MAX_MULTIPLES, rules.max_multiple),
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!(
+                        "四带两对: {}",
+                        if rules.four_with_two_pairs {
+                            "开"
+                        } else {
+                            "关"
+                        }
+                    ),
+                    RoomRules {
+                        four_with_two_pairs: !rules.four_with_two_pairs,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("飞机带炸: {}", if rules.bomb_wings { "开" } else { "关" }),
+                    RoomRules {
+                        bomb_wings: !rules.bomb_wings,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("加倍: {}", if rules.doubling { "开" } else { "关" }),
+                    RoomRules {
+                        doubling: !rules.doubling,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("明牌: {}", if rules.show_hand { "开" } else { "关" }),
+                    RoomRules {
+                        show_hand: !rules.show_hand,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    match rules.turn_seconds {
+                        Some(seconds) => format!("出牌时间: {}秒", seconds),
+                        None => "出牌时间: 不限".to_string(),
+                    },
+                    RoomRules {
+                        turn_seconds: cycle(&TURN_SECONDS, rules.turn_seconds),
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("癞子: {}", if rules.laizi { "开" } else { "关" }),
+                    RoomRules {
+                        laizi: !rules.laizi,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("不洗牌: {}", if rules.no_shuffle { "开" } else { "关" }),
+                    RoomRules {
+                        no_shuffle: !rules.no_shuffle,
+                        ..rules.clone()
+                    },
+                ),
+                (
+                    format!("记牌器: {}", if rules.card_counter { "开" } else { "关" }),
+                    RoomRules {
+                        card_counter: !rules.card_counter,
+                        ..rules.clone()
+                    },
+                ),
+            ];
+            for (label, rules) in options {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                                ..Default::default()
+                            },
+                            background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                            ..Default::default()
+                        },
+                        RoomButton::Rules(rules),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                    });
+            }
+        });
+    }
+}
+
+// 座位上的状态文字 叫地主阶段显示最后一次叫的结果
+fn seat_status(room: &Room, seat: usize) -> Option<String> {
+    let game = room.game.as_ref()?;
+    match game.phase {
+        Phase::Bidding => game
+            .bids
+            .iter()
+            .rposition(|(s, _)| *s == seat)
+            .map(|index| game.bids[index].1.label(game.robbing(index))),
+        Phase::Doubling => game.doubles[seat].map(|double| double.label().to_string()),
+        Phase::Playing => game
+            .trick()
+            .iter()
+            .rev()
+            .find(|record| record.seat == seat)
+            .filter(|record| record.pattern.is_none())
+            .map(|_| "不出".to_string()),
+        Phase::Finished => game
+            .result
+            .as_ref()
+            .map(|result| format!("{:+} 金币", result.coins[seat])),
+    }
+}
+
+// 座位数对应的座位和剩余牌数位置
+fn seat_layout(seats: usize) -> (&'static [[f32; 2]], &'static [[f32; 2]]) {
+    if seats == FOUR_PLAYER_POSITION.len() {
+        (&FOUR_PLAYER_POSITION, &FOUR_PLAYER_CARD_COUNT_POSITION)
+    } else {
+        (&PLAYER_POSITION, &CARD_COUNT_POSITION)
+    }
+}
+
+// 放到相对位置对应的地方 玩法没有这个位置时隐藏
+fn place(style: &mut Style, positions: &[[f32; 2]], index: usize) {
+    match positions.get(index) {
+        Some([top, left]) => {
+            style.display = Display::Flex;
+            style.top = Val::Percent(*top);
+            style.left = Val::Percent(*left);
+        }
+        None => style.display = Display::None,
+    }
+}
+
+// 按座位显示玩家 自己在下方 下家和上家分列两侧 四人时对家在上方
+fn refresh_seats(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    plists: Res<Assets<PlistAtlas>>,
+    room: Res<Room>,
+    local: Res<Player>,
+    mut seats: Query<(Entity, &SeatView, &mut Style), Without<SeatCardCount>>,
+    mut counts: Query<(&mut Text, &mut Style, &SeatCardCount)>,
+    added: Query<(), Added<SeatView>>,
+) {
+    if !room.is_changed() && added.is_empty() {
+        return;
+    }
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    let status_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 26.,
+        color: Color::ORANGE,
+    };
+    let Some(public_ui) = plists.get(&assets.public_ui) else {
+        return;
+    };
+    let (seat_positions, count_positions) = seat_layout(room.players.len());
+    for (entity, view, mut style) in seats.iter_mut() {
+        place(&mut style, seat_positions, view.0);
+        commands.entity(entity).despawn_descendants();
+        let Some(room_player) = room
+            .players
+            .iter()
+            .flatten()
+            .find(|p| room.relative_seat(p.room_position as usize, local.id) == view.0)
+        else {
+            continue;
+        };
+        let seat = room_player.room_position as usize;
+        let game = room.game.as_ref();
+        commands.entity(entity).with_children(|parent| {
+            parent
+                .spawn(ImageBundle {
+                    image: assets.room_touxiang.clone().into(),
+                    style: Style {
+                        width: Val::Px(80.),
+                        height: Val::Px(80.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    public_ui.spawn_image(
+                        parent,
+                        room_player.player.profile.avatar.head_frame(),
+                        Style {
+                            position_type: PositionType::Absolute,
+                            top: Val::Px(4.),
+                            left: Val::Px(4.),
+                            width: Val::Px(72.),
+                            height: Val::Px(72.),
+                            ..Default::default()
+                        },
+                    );
+                    if game.is_some_and(|game| game.landlord == Some(seat)) {
+                        parent.spawn(ImageBundle {
+                            image: assets.landlord_badge.clone().into(),
+                            style: Style {
+                                position_type: PositionType::Absolute,
+                                top: Val::Px(-6.),
+                                right: Val::Px(-6.),
+                                width: Val::Px(36.),
+                                height: Val::Px(36.),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        });
+                    }
+                    // 加倍或超级加倍的标记
+                    if let Some(double) = game
+                        .and_then(|game| game.doubles[seat])
+                        .filter(|double| *double != Doubling::No)
+                    {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    bottom: Val::Px(-4.),
+                                    right: Val::Px(-10.),
+                                    padding: UiRect::axes(Val::Px(4.), Val::Px(1.)),
+                                    ..Default::default()
+                                },
+                                background_color: Color::CRIMSON.into(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    double.label(),
+                                    TextStyle {
+                                        font_size: 16.,
+                                        ..text_style.clone()
+                                    },
+                                ));
+                            });
+                    }
+                });
+            // 轮到的玩家名字高亮
+            let color = if game.is_some_and(|game| game.turn == seat) {
+                Color::GOLD
+            } else {
+                Color::WHITE
+            };
+            parent.spawn(TextBundle::from_section(
+                room_player.player.name(),
+                TextStyle {
+                    color,
+                    ..text_style.clone()
+                },
+            ));
+            // 金币
+            parent
+                .spawn(ImageBundle {
+                    image: assets.room_money_frame.clone().into(),
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(4.),
+                        padding: UiRect::new(Val::Px(2.), Val::Px(10.), Val::Px(1.), Val::Px(1.)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(ImageBundle {
+                        image: assets.yuanbao_icon.clone().into(),
+                        style: Style {
+                            width: Val::Px(24.),
+                            height: Val::Px(18.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                    parent.spawn(TextBundle::from_section(
+                        room_player.player.profile.coins.to_string(),
+                        TextStyle {
+                            font_size: 18.,
+                            color: Color::GOLD,
+                            ..text_style.clone()
+                        },
+                    ));
+                });
+            if room.waiting() && room_player.ready {
+                parent.spawn(ImageBundle {
+                    image: assets.pm_ready.clone().into(),
+                    style: Style {
+                        width: Val::Px(64.),
+                        height: Val::Px(28.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            } else if let Some(status) = seat_status(&room, seat) {
+                parent.spawn(TextBundle::from_section(status, status_style.clone()));
+            }
+        });
+    }
+    for (mut text, mut style, count) in counts.iter_mut() {
+        place(&mut style, count_positions, count.0);
+        let cards = room.game.as_ref().and_then(|game| {
+            (0..game.seats())
+                .find(|seat| room.relative_seat(*seat, local.id) == count.0)
+                .map(|seat| game.hands[seat].len())
+        });
+        text.sections[0].value = match cards {
+            Some(cards) => format!("{} 张", cards),
+            None => String::new(),
+        };
+    }
+}
+
+// 房间成员列表 房主可以踢出其他玩家
+fn refresh_members(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    room: Res<Room>,
+    local: Res<Player>,
+    list: Query<Entity, With<RoomMemberList>>,
+    added: Query<(), Added<RoomMemberList>>,
+) {
+    if !room.is_changed() && added.is_empty() {
+        return;
+    }
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 24.,
+        color: Color::WHITE,
+    };
+    for entity in list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for room_player in room.players.iter().flatten() {
+                let peer = room_player.player.id;
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(8.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        let mut name = room_player.player.name();
+                        if room.is_owner(peer) {
+                            name.push_str(" (房主)");
+                        }
+                        parent.spawn(TextBundle::from_section(name, text_style.clone()));
+                        if room.is_owner(local.id) && peer != local.id {
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(8.), Val::Px(2.)),
+                                            ..Default::default()
+                                        },
+                                        background_color: Color::ORANGE_RED.into(),
+                                        ..Default::default()
+                                    },
+                                    RoomButton::Kick(peer),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section(
+                                        "踢出",
+                                        text_style.clone(),
+                                    ));
+                                });
+                        }
+                    });
+            }
+        });
     }
-    // 更新房间玩家 根据room_position 计算出用户的位置
-    if let Some(local_room_player) = room.players.iter().find(|x| {
-        if let Some(p) = x.to_owned() {
-            p.player == *local
-        } else {
-            false
-        }
-    }) {
-        // local_room_player.
-    }
-    // let local_index = room
-    //     .players
-    //     .iter()
-    //     .position(|p| p.is_some() && p.clone().unwrap() == *local);
-    // if let Some(index) = local_index {
-    //     let sort_players = match index {
-    //         0 => [
-    //             room.players[0].clone(),
-    //             room.players[1].clone(),
-    //             room.players[2].clone(),
-    //         ],
-    //         1 => [
-    //             room.players[1].clone(),
-    //             room.players[2].clone(),
-    //             room.players[0].clone(),
-    //         ],
-    //         2 => [
-    //             room.players[2].clone(),
-    //             room.players[0].clone(),
-    //             room.players[1].clone(),
-    //         ],
-    //         _ => unreachable!(),
-    //     };
-    //     for index in 0..sort_players.len() {
-    //         if let Some(player) = sort_players[index].clone() {
-    //             commands
-    //                 .spawn(TextBundle {
-    //                     text: Text::from_section(
-    //                         player.id.to_string().get(0..7).unwrap_or("player"),
-    //                         TextStyle {
-    //                             font: assets.font.clone(),
-    //                             font_size: 24.0,
-    //                             color: Color::GOLD,
-    //                             ..Default::default()
-    //                         },
-    //                     )
-    //                     .with_alignment(TextAlignment::Center),
-    //                     style: Style {
-    //                         position_type: PositionType::Absolute,
-    //                         top: Val::Percent(PLAYER_POSITION[index][0]),
-    //                         left: Val::Percent(PLAYER_POSITION[index][1]),
-    //                         ..Default::default()
-    //                     },
-    //                     ..default()
-    //                 })
-    //                 .with_children(|builder| {
-    //                     builder.spawn(ImageBundle {
-    //                         image: assets.room_touxiang.clone().into(),
-    //                         style: Style {
-    //                             position_type: PositionType::Relative,
-    //                             top: Val::Px(-85.),
-    //                             left: Val::Px(-5.),
-    //                             ..Default::default()
-    //                         },
-    //                         ..Default::default()
-    //                     });
-    //                 });
-    //         }
-    //     }
-    //     if room.players.iter().filter(|p| p.is_some()).count() >= 3 {
-    //         println!("playing................");
-    //         state.set(AppState::Playing);
-    //     }
-    // }
-    room.changed = false;
 }
 
 pub fn receive_events(
-    _lobby: ResMut<Lobby>,
+    mut commands: Commands,
     mut room: ResMut<Room>,
     mut socket: ResMut<Socket>,
+    mut tip: ResMut<LobbyTip>,
+    mut state: ResMut<NextState<AppState>>,
+    mut lobby: ResMut<Lobby>,
+    mut stats_server: ResMut<StatsServer>,
     local: Res<Player>,
 ) {
-    let binding = socket.receive_unreliable();
-    let events = Vec::from_iter(
-        binding.iter(), // .filter(|e| e.src != lobby.socket.id().unwrap()),
-    );
-    for AddressedEvent { src, event } in events {
+    for AddressedEvent { src, event } in socket.receive().iter() {
         match event {
-            Event::JoinRoom => {
-                if room.join(src.clone()) {
-                    println!("{:?}", room.players);
-                    let mut peers = room
-                        .players
-                        .iter()
-                        .filter(|p| {
-                            p.is_some()
-                                && <std::option::Option<RoomPlayer> as Clone>::clone(&p)
-                                    .unwrap()
-                                    .player
-                                    .id
-                                    != local.id
-                        })
-                        .map(|p| p.clone().unwrap().player.id)
-                        .collect::<Vec<PeerId>>();
-                    peers.push(src.id);
-                    socket.send_unreliable(
+            // 加入请求由房主处理 结果走可靠通道 丢失后房主会留着没人的座位
+            Event::JoinRoom { code, password }
+                if *code == room.code && room.owner.player == *local =>
+            {
+                if let Err(err) = room.join(src.clone(), password.as_deref()) {
+                    socket.send_reliable(
+                        AddressedEvent {
+                            src: local.clone(),
+                            event: Event::JoinRoomRejected(err),
+                        },
+                        vec![src.id],
+                    );
+                    continue;
+                }
+                let peers = room
+                    .players
+                    .iter()
+                    .flatten()
+                    .map(|p| p.player.id)
+                    .filter(|id| *id != local.id)
+                    .collect::<Vec<PeerId>>();
+                for peer in peers {
+                    socket.send_reliable(
                         AddressedEvent {
                             src: local.clone(),
-                            event: Event::JoinRoomSuccess(room.clone()),
+                            event: Event::JoinRoomSuccess(room.member_view(peer)),
                         },
-                        peers,
+                        vec![peer],
                     );
                 }
             }
-            Event::Test(_) => todo!(),
+            Event::LeaveRoom(code) if *code == room.code && room.is_owner(local.id) => {
+                room.leave(src.id);
+            }
+            Event::Ready { ready, show_hand } if room.is_owner(local.id) => {
+                room.set_ready(src.id, *ready, *show_hand)
+            }
+            Event::GameAction(action) if room.is_owner(local.id) => {
+                apply_action(&mut room, src.id, action)
+            }
+            // 只有房主能踢人和同步房间 防止其他客户端伪造
+            Event::Kicked(code) if *code == room.code && src.id == room.owner.player.id => {
+                tip.show("你已被房主移出房间");
+                commands.remove_resource::<Room>();
+                state.set(AppState::Lobby);
+            }
+            // 在房间里时大厅不处理消息 其他房间的变化转给大厅
+            Event::SyncRoom(synced) if *synced != *room => lobby.sync_room(synced.clone()),
+            Event::DissolveRoom(code) if *code != room.code => lobby.remove_room(*code),
+            Event::StatsServerOnline => stats_server.announced(src),
+            // 其他成员跟随房主同步房间信息 同步的房间里已经没有自己时回到大厅
+            Event::SyncRoom(synced) | Event::JoinRoomSuccess(synced)
+                if !room.sync(src.id, synced, local.id) =>
+            {
+                commands.remove_resource::<Room>();
+                state.set(AppState::Lobby);
+            }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::Profile;
+
+    fn player(id: u8) -> Player {
+        let peer = format!("\"00000000-0000-0000-0000-{:012}\"", id);
+        let profile = Profile {
+            uid: id as u64,
+            nickname: format!("玩家{}", id),
+            avatar: Default::default(),
+            voice: Default::default(),
+            coins: 1000,
+        };
+        Player::new(serde_json::from_str(&peer).unwrap(), profile)
+    }
+
+    #[test]
+    fn syncs_only_from_owner() {
+        let (owner, member, other) = (player(1), player(2), player(3));
+        let mut room = Room::new(owner.clone(), 123456);
+        room.join(member.clone(), None).unwrap();
+        room.join(other.clone(), None).unwrap();
+
+        // 非房主伪造的同步被忽略
+        let mut forged = room.clone();
+        forged.leave(member.id);
+        assert!(room.sync(other.id, &forged, member.id));
+        assert!(room.is_member(member.id));
+
+        // 房主同步时跟随 同步的房间里没有自己时离开
+        assert!(!room.sync(owner.id, &forged, member.id));
+        let mut transferred = room.clone();
+        transferred.leave(owner.id);
+        assert!(room.sync(owner.id, &transferred, member.id));
+        assert!(room.is_owner(member.id));
+
+        // 转让后只接受原房主指定的新房主
+        let mut forged = room.clone();
+        forged.state = RoomState::Gaming;
+        room.sync(owner.id, &forged, other.id);
+        assert_ne!(room.state, RoomState::Gaming);
+        room.sync(member.id, &forged, other.id);
+        assert_eq!(room.state, RoomState::Gaming);
+    }
+
+    #[test]
+    fn leaving_aborts_only_unfinished_games() {
+        let (owner, a, b) = (player(1), player(2), player(3));
+        let mut room = Room::new(owner.clone(), 123456);
+        room.join(a.clone(), None).unwrap();
+        room.join(b.clone(), None).unwrap();
+        room.start_game();
+        room.leave(b.id);
+        assert!(room.game.is_none());
+
+        room.join(b.clone(), None).unwrap();
+        room.start_game();
+        while let Some(game) = room
+            .game
+            .as_ref()
+            .filter(|game| game.phase != Phase::Finished)
+        {
+            let action = match game.phase {
+                Phase::Bidding => GameAction::Bid(game.bid_options()[0]),
+                _ => game.timeout_action(),
+            };
+            room.apply_seat_action(game.turn, &action).unwrap();
+        }
+        // 结算界面上离开不影响结果
+        room.leave(b.id);
+        let game = room.game.as_ref().unwrap();
+        assert_eq!(game.phase, Phase::Finished);
+        assert!(game.result.is_some());
+    }
+}
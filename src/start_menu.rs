@@ -1,9 +1,10 @@
 use bevy::prelude::*;
 
 use crate::{
-    common::{despawn_screen, AppState, MenuButton, MyAssets, Socket},
+    common::{despawn_screen, update_peers, AppMode, AppState, MenuButton, MyAssets, Socket},
     lobby::Lobby,
-    player::Player,
+    player::{Player, Profile},
+    stats::{StatsServer, STATS_SERVER_UID},
 };
 use bevy_matchbox::prelude::*;
 
@@ -12,21 +13,43 @@ pub(crate) struct StartMenuPlugin;
 
 impl Plugin for StartMenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::StartMenu), setup)
-            .add_systems(Update, update.run_if(in_state(AppState::StartMenu)))
-            .add_systems(
-                OnExit(AppState::StartMenu),
-                despawn_screen::<StartMenuPlugin>,
-            );
+        // 已有Socket时不再连接 测试中预先放入不联网的Socket
+        app.add_systems(
+            OnEnter(AppState::StartMenu),
+            connect.run_if(not(resource_exists::<Socket>())),
+        )
+        .add_systems(PreUpdate, update_peers.run_if(resource_exists::<Socket>()))
+        .add_systems(
+            OnEnter(AppState::StartMenu),
+            setup.run_if(resource_equals(AppMode::Windowed)),
+        )
+        .add_systems(Update, update.run_if(in_state(AppState::StartMenu)))
+        .add_systems(
+            Update,
+            auto_login
+                .run_if(in_state(AppState::StartMenu))
+                .run_if(resource_equals(AppMode::Headless)),
+        )
+        .add_systems(
+            OnExit(AppState::StartMenu),
+            despawn_screen::<StartMenuPlugin>,
+        );
     }
 }
 
-pub fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+pub fn connect(mut commands: Commands) {
     let room_url = "ws://47.108.130.232:3536/poker";
     info!("connecting to matchbox server: {room_url}");
-    let socket = MatchboxSocket::new_ggrs(room_url);
+    let socket = MatchboxSocket::from(
+        WebRtcSocketBuilder::new(room_url)
+            .add_channel(ChannelConfig::unreliable())
+            .add_channel(ChannelConfig::reliable()),
+    );
     let socket = Socket::new(socket);
     commands.insert_resource(socket);
+}
+
+pub fn setup(mut commands: Commands, assets: Res<MyAssets>) {
     commands
         .spawn((
             NodeBundle {
@@ -106,11 +129,6 @@ pub fn setup(mut commands: Commands, assets: Res<MyAssets>) {
                     });
                 });
         });
-    // commands.spawn(AudioBundle {
-    //     source: assets.login_bg.clone(),
-    //     // settings: PlaybackSettings::LOOP,
-    //     settings: PlaybackSettings::ONCE,
-    // });
 }
 
 pub fn update(
@@ -124,10 +142,7 @@ pub fn update(
             match button {
                 MenuButton::Traveler => {
                     if let Some(peer) = socket.unreliable_id() {
-                        let lobby = Lobby::new();
-                        commands.insert_resource(lobby);
-                        commands.insert_resource(Player::new(peer));
-                        state.set(AppState::Lobby);
+                        enter_lobby(&mut commands, &mut state, peer, Profile::load());
                     }
                 }
                 MenuButton::Weixin => {
@@ -137,3 +152,31 @@ pub fn update(
         }
     }
 }
+
+// 无窗口模式下 连接成功后直接以游客身份进入大厅
+pub fn auto_login(
+    mut commands: Commands,
+    mut state: ResMut<NextState<AppState>>,
+    mut socket: ResMut<Socket>,
+    stats_server: Res<StatsServer>,
+) {
+    if let Some(peer) = socket.unreliable_id() {
+        let mut profile = Profile::load();
+        // 排行榜服务器使用固定的编号 客户端据此认出它
+        if *stats_server == StatsServer::Local {
+            profile.uid = STATS_SERVER_UID;
+        }
+        enter_lobby(&mut commands, &mut state, peer, profile);
+    }
+}
+
+fn enter_lobby(
+    commands: &mut Commands,
+    state: &mut NextState<AppState>,
+    peer: PeerId,
+    profile: Profile,
+) {
+    commands.insert_resource(Lobby::new());
+    commands.insert_resource(Player::new(peer, profile));
+    state.set(AppState::Lobby);
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_matchbox::prelude::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{in_room, AddressedEvent, Event, Socket},
+    game::Phase,
+    player::Player,
+    room::Room,
+    storage,
+};
+
+// 本地保存战绩的键
+const STATS_KEY: &str = "stats";
+// 排行榜显示的人数
+pub const LEADERBOARD_LEN: usize = 10;
+
+pub struct StatsPlugin;
+
+// 一名玩家在一局中的结算 由结束的牌局生成
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettlementRecord {
+    pub uid: u64,
+    pub nickname: String,
+    pub landlord: bool,
+    pub won: bool,
+    // 本局打出的炸弹和王炸
+    pub bombs: u32,
+    // 赢家打出了春天或反春
+    pub spring: bool,
+    pub coins: i64,
+}
+
+impl SettlementRecord {
+    // 已结束牌局中每个座位的结算 牌局没有结束时为空
+    pub fn from_room(room: &Room) -> Vec<Self> {
+        let Some(game) = room
+            .game
+            .as_ref()
+            .filter(|game| game.phase == Phase::Finished)
+        else {
+            return vec![];
+        };
+        let Some(result) = &game.result else {
+            return vec![];
+        };
+        let landlord = game.landlord.unwrap_or(result.winner);
+        room.players
+            .iter()
+            .enumerate()
+            .filter_map(|(seat, room_player)| {
+                let profile = &room_player.as_ref()?.player.profile;
+                let won = (seat == landlord) == result.landlord_won;
+                Some(Self {
+                    uid: profile.uid,
+                    nickname: profile.nickname.clone(),
+                    landlord: seat == landlord,
+                    won,
+                    bombs: game
+                        .plays
+                        .iter()
+                        .filter(|record| {
+                            record.seat == seat
+                                && record.pattern.as_ref().is_some_and(|p| p.is_bomb())
+                        })
+                        .count() as u32,
+                    spring: won && result.spring,
+                    coins: result.coins.get(seat).copied().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+}
+
+// 一名玩家的长期战绩
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerStats {
+    // 最近一局使用的昵称
+    pub nickname: String,
+    pub games: u32,
+    pub landlord_games: u32,
+    pub landlord_wins: u32,
+    pub farmer_games: u32,
+    pub farmer_wins: u32,
+    pub bombs: u32,
+    pub springs: u32,
+    // 单局赢得最多的金币
+    pub biggest_win: i64,
+    // 累计输赢的金币
+    pub total_coins: i64,
+    // 正数为连胜 负数为连败
+    pub streak: i32,
+}
+
+impl PlayerStats {
+    pub fn record(&mut self, record: &SettlementRecord) {
+        self.nickname = record.nickname.clone();
+        self.games += 1;
+        if record.landlord {
+            self.landlord_games += 1;
+            self.landlord_wins += record.won as u32;
+        } else {
+            self.farmer_games += 1;
+            self.farmer_wins += record.won as u32;
+        }
+        self.bombs += record.bombs;
+        self.springs += record.spring as u32;
+        self.biggest_win = self.biggest_win.max(record.coins);
+        self.total_coins += record.coins;
+        self.streak = match (record.won, self.streak) {
+            (true, streak) if streak > 0 => streak + 1,
+            (true, _) => 1,
+            (false, streak) if streak < 0 => streak - 1,
+            (false, _) => -1,
+        };
+    }
+
+    pub fn wins(&self) -> u32 {
+        self.landlord_wins + self.farmer_wins
+    }
+
+    pub fn win_rate(&self) -> String {
+        rate(self.wins(), self.games)
+    }
+
+    pub fn landlord_win_rate(&self) -> String {
+        rate(self.landlord_wins, self.landlord_games)
+    }
+
+    pub fn farmer_win_rate(&self) -> String {
+        rate(self.farmer_wins, self.farmer_games)
+    }
+
+    pub fn streak_label(&self) -> String {
+        match self.streak {
+            streak if streak > 0 => format!("{}连胜", streak),
+            streak if streak < 0 => format!("{}连败", -streak),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+// 百分比胜率 没有对局时显示-
+fn rate(wins: u32, games: u32) -> String {
+    if games == 0 {
+        return "-".to_string();
+    }
+    format!("{}%", wins * 100 / games)
+}
+
+// 排行榜服务器的玩家编号 连接编号每次连接都会变 客户端按这个编号认出服务器
+pub const STATS_SERVER_UID: u64 = 1;
+
+// 排行榜服务器 排行榜只信任这一个连接
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Resource)]
+pub enum StatsServer {
+    /**还没有收到服务器上线的消息 只显示本地记录 */
+    #[default]
+    Offline,
+    /**本机就是服务器 汇总各房主发来的结算并回复排行榜 */
+    Local,
+    /**服务器的连接 只接受它发来的排行榜 结算也只发给它 */
+    Online(PeerId),
+}
+
+impl StatsServer {
+    // `--stats-server`以服务器身份启动
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Self {
+        if args.into_iter().any(|arg| arg == "--stats-server") {
+            Self::Local
+        } else {
+            Self::Offline
+        }
+    }
+
+    // 收到上线消息 发送者带有服务器的编号时记下它的连接
+    pub fn announced(&mut self, src: &Player) {
+        if *self != Self::Local && src.profile.uid == STATS_SERVER_UID {
+            *self = Self::Online(src.id);
+        }
+    }
+
+    // 已经连上的服务器
+    pub fn peer(&self, socket: &Socket) -> Option<PeerId> {
+        match self {
+            Self::Online(peer) if socket.connected_peers().contains(peer) => Some(*peer),
+            _ => None,
+        }
+    }
+
+    pub fn is(&self, peer: PeerId) -> bool {
+        *self == Self::Online(peer)
+    }
+}
+
+// 所有见过的玩家的战绩 按玩家编号保存在本地
+// 排行榜服务器收集各房主发来的结算 是排行榜的权威数据
+#[derive(Debug, Default, Resource, Serialize, Deserialize)]
+pub struct StatsStore {
+    pub players: HashMap<u64, PlayerStats>,
+    // 已经记录过的牌局 (房间号, 洗牌种子) 避免重复记录
+    #[serde(skip)]
+    recorded: Option<(u32, u64)>,
+}
+
+impl StatsStore {
+    pub fn load() -> Self {
+        storage::load(STATS_KEY).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        storage::save(STATS_KEY, self);
+    }
+
+    pub fn record(&mut self, records: &[SettlementRecord]) {
+        for record in records {
+            self.players.entry(record.uid).or_default().record(record);
+        }
+    }
+
+    pub fn get(&self, uid: u64) -> PlayerStats {
+        self.players.get(&uid).cloned().unwrap_or_default()
+    }
+
+    // 按累计输赢的金币排名 相同时比较胜场
+    pub fn leaderboard(&self) -> Vec<(u64, PlayerStats)> {
+        let mut players: Vec<(u64, PlayerStats)> = self
+            .players
+            .iter()
+            .map(|(uid, stats)| (*uid, stats.clone()))
+            .collect();
+        players.sort_by(|(_, a), (_, b)| {
+            b.total_coins
+                .cmp(&a.total_coins)
+                .then(b.wins().cmp(&a.wins()))
+        });
+        players.truncate(LEADERBOARD_LEN);
+        players
+    }
+
+    // 合并服务器发来的排行榜 服务器记录的局数更多时以服务器为准 自己的战绩只用本地记录
+    pub fn merge(&mut self, leaderboard: &[(u64, PlayerStats)], local_uid: u64) {
+        for (uid, stats) in leaderboard {
+            if *uid == local_uid {
+                continue;
+            }
+            let local = self.players.entry(*uid).or_default();
+            if stats.games > local.games {
+                *local = stats.clone();
+            }
+        }
+    }
+}
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StatsStore::load())
+            .init_resource::<StatsServer>()
+            .add_systems(Update, record_settlement.run_if(in_room));
+    }
+}
+
+// 牌局结束后记录房间里所有玩家的战绩 房主把结算发给排行榜服务器汇总
+fn record_settlement(
+    room: Res<Room>,
+    local: Res<Player>,
+    server: Res<StatsServer>,
+    mut store: ResMut<StatsStore>,
+    mut socket: ResMut<Socket>,
+) {
+    if !room.is_changed() {
+        return;
+    }
+    let Some(game) = &room.game else {
+        return;
+    };
+    let key = (room.code, game.seed);
+    if store.recorded == Some(key) {
+        return;
+    }
+    let records = SettlementRecord::from_room(&room);
+    if records.is_empty() {
+        return;
+    }
+    store.recorded = Some(key);
+    store.record(&records);
+    store.save();
+    let Some(server) = server.peer(&socket).filter(|_| room.is_owner(local.id)) else {
+        return;
+    };
+    socket.send_reliable(
+        AddressedEvent {
+            src: local.clone(),
+            event: Event::Settlement(records),
+        },
+        vec![server],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(landlord: bool, won: bool, coins: i64) -> SettlementRecord {
+        SettlementRecord {
+            uid: 1,
+            nickname: "玩家".to_string(),
+            landlord,
+            won,
+            bombs: 1,
+            spring: false,
+            coins,
+        }
+    }
+
+    #[test]
+    fn records_rates_and_streaks() {
+        let mut store = StatsStore::default();
+        store.record(&[record(true, true, 300)]);
+        store.record(&[record(false, true, 100)]);
+        store.record(&[record(false, false, -200)]);
+        let stats = store.get(1);
+        assert_eq!(stats.games, 3);
+        assert_eq!(stats.landlord_win_rate(), "100%");
+        assert_eq!(stats.farmer_win_rate(), "50%");
+        assert_eq!(stats.win_rate(), "66%");
+        assert_eq!(stats.bombs, 3);
+        assert_eq!(stats.biggest_win, 300);
+        assert_eq!(stats.total_coins, 200);
+        assert_eq!(stats.streak_label(), "1连败");
+
+        // 服务器局数更多时覆盖本地 自己的战绩不会被覆盖
+        let server = PlayerStats {
+            games: 10,
+            ..Default::default()
+        };
+        store.merge(&[(1, server.clone()), (2, server.clone())], 3);
+        assert_eq!(store.get(1), server);
+        assert_eq!(store.leaderboard().len(), 2);
+        store.merge(&[(3, server.clone())], 3);
+        assert_eq!(store.get(3), PlayerStats::default());
+    }
+
+    #[test]
+    fn trusts_only_the_announced_stats_server() {
+        let player = |uid: u64, id: &str| {
+            let profile = crate::player::Profile {
+                uid,
+                nickname: String::new(),
+                avatar: Default::default(),
+                voice: Default::default(),
+                coins: 0,
+            };
+            let peer = format!("\"00000000-0000-0000-0000-{}\"", id);
+            Player::new(serde_json::from_str(&peer).unwrap(), profile)
+        };
+        let args = |args: &[&str]| StatsServer::from_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(args(&["poker", "--headless"]), StatsServer::Offline);
+        assert_eq!(args(&["poker", "--stats-server"]), StatsServer::Local);
+
+        // 只认带有服务器编号的上线消息 服务器重启换了连接也能认出来
+        let mut server = StatsServer::default();
+        let other = player(2, "000000000002");
+        server.announced(&other);
+        assert!(!server.is(other.id));
+        let first = player(STATS_SERVER_UID, "000000000001");
+        server.announced(&first);
+        assert!(server.is(first.id));
+        let restarted = player(STATS_SERVER_UID, "000000000003");
+        server.announced(&restarted);
+        assert!(server.is(restarted.id) && !server.is(first.id));
+
+        // 服务器自己不会把别人当成服务器
+        let mut local = StatsServer::Local;
+        local.announced(&restarted);
+        assert_eq!(local, StatsServer::Local);
+    }
+}
@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+// 本地保存的数据 桌面端存在用户目录下 浏览器中存在localStorage
+// 读取失败时返回None 由调用方使用默认值
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(key: &str) -> std::path::PathBuf {
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("APPDATA"))
+        .map(std::path::PathBuf::from)
+        .unwrap_or_default();
+    home.join(".poker").join(format!("{}.json", key))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let text = std::fs::read_to_string(path(key)).ok()?;
+    serde_json::from_str(&text)
+        .map_err(|err| warn!("读取 {} 失败: {}", key, err))
+        .ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let path = path(key);
+    let result = serde_json::to_string(value)
+        .map_err(|err| err.to_string())
+        .and_then(|text| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            std::fs::write(&path, text).map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        warn!("保存 {} 失败: {}", key, err);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let text = local_storage()?
+        .get_item(&format!("poker.{}", key))
+        .ok()??;
+    serde_json::from_str(&text)
+        .map_err(|err| warn!("读取 {} 失败: {}", key, err))
+        .ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let Some(storage) = local_storage() else {
+        return;
+    };
+    let saved = serde_json::to_string(value)
+        .ok()
+        .and_then(|text| storage.set_item(&format!("poker.{}", key), &text).ok());
+    if saved.is_none() {
+        warn!("保存 {} 失败", key);
+    }
+}
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+
+use crate::{
+    card::{get_sprite_index, CardAtlas},
+    common::{on_table, AppMode, AppState, MyAssets},
+    game::PlayRecord,
+    hand::sort_by_rank,
+    player::Player,
+    room::{Room, RoomUIComponent},
+};
+
+// 打出的牌的缩放
+const PLAYED_CARD_SCALE: f32 = 0.5;
+// 打出的牌相邻间距
+const PLAYED_CARD_SPACING: f32 = 24.;
+// 出牌区域中心 依次为自己、下家、上家
+const PLAY_AREA_POSITION: [[f32; 2]; 3] = [[0., -40.], [-300., 110.], [300., 110.]];
+// 四人时依次为自己、下家、对家、上家
+const FOUR_PLAYER_PLAY_AREA_POSITION: [[f32; 2]; 4] =
+    [[0., -40.], [-300., 110.], [0., 170.], [300., 110.]];
+
+// 明牌的手牌缩放和间距
+const SHOWN_CARD_SCALE: f32 = 0.4;
+const SHOWN_CARD_SPACING: f32 = 16.;
+// 明牌的手牌中心 依次为自己、下家、上家 自己的手牌不在这里显示
+const SHOWN_HAND_POSITION: [[f32; 2]; 3] = [[0., 0.], [-330., 220.], [330., 220.]];
+const FOUR_PLAYER_SHOWN_HAND_POSITION: [[f32; 2]; 4] =
+    [[0., 0.], [-330., 220.], [0., 280.], [330., 220.]];
+
+pub struct TablePlugin;
+
+// 出牌区域中的牌和不出标记 每次房间变化时重建
+#[derive(Component)]
+pub struct PlayedCard;
+
+// 其他座位明牌后显示的手牌 每次房间变化时重建
+#[derive(Component)]
+pub struct ShownHandCard;
+
+// 出牌记录面板
+#[derive(Component)]
+pub struct HistoryPanel;
+
+// 出牌记录列表 面板收起时隐藏
+#[derive(Component)]
+pub struct HistoryList;
+
+#[derive(Component)]
+pub struct HistoryToggle;
+
+// 癞子玩法中本局的癞子
+#[derive(Component)]
+pub struct LaiziText;
+
+impl Plugin for TablePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnTransition {
+                from: AppState::Lobby,
+                to: AppState::InRoom,
+            },
+            setup.run_if(resource_equals(AppMode::Windowed)),
+        )
+        .add_systems(
+            OnEnter(AppState::Replay),
+            setup.run_if(resource_equals(AppMode::Windowed)),
+        )
+        .add_systems(
+            Update,
+            (
+                refresh_play_area,
+                refresh_shown_hands,
+                refresh_history,
+                refresh_laizi,
+                toggle_history,
+            )
+                .run_if(on_table)
+                .run_if(resource_exists::<CardAtlas>()),
+        );
+    }
+}
+
+fn setup(mut commands: Commands, assets: Res<MyAssets>) {
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 22.,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: assets.font.clone(),
+                font_size: 28.,
+                color: Color::GOLD,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(3.),
+            right: Val::Percent(16.),
+            ..Default::default()
+        }),
+        LaiziText,
+        RoomUIComponent,
+    ));
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(8.),
+                    left: Val::Percent(2.),
+                    width: Val::Px(320.),
+                    flex_direction: FlexDirection::Column,
+                    ..Default::default()
+                },
+                z_index: ZIndex::Global(5),
+                ..Default::default()
+            },
+            HistoryPanel,
+            RoomUIComponent,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                            ..Default::default()
+                        },
+                        background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                        ..Default::default()
+                    },
+                    HistoryToggle,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("出牌记录", text_style.clone()));
+                });
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        max_height: Val::Px(360.),
+                        overflow: Overflow::clip_y(),
+                        padding: UiRect::all(Val::Px(8.)),
+                        row_gap: Val::Px(4.),
+                        ..Default::default()
+                    },
+                    background_color: Color::rgba(0., 0., 0., 0.6).into(),
+                    visibility: Visibility::Hidden,
+                    ..Default::default()
+                },
+                HistoryList,
+            ));
+        });
+}
+
+// 每个座位在这一轮最后一次出牌或不出
+fn latest_records(records: &[PlayRecord], seats: usize) -> Vec<&PlayRecord> {
+    (0..seats)
+        .filter_map(|seat| records.iter().rev().find(|record| record.seat == seat))
+        .collect()
+}
+
+// 出牌区域 新一轮开始时清空
+fn refresh_play_area(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    card_atlas: Res<CardAtlas>,
+    room: Res<Room>,
+    local: Res<Player>,
+    query: Query<Entity, With<PlayedCard>>,
+) {
+    if !room.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(game) = &room.game else {
+        return;
+    };
+    let positions: &[[f32; 2]] = if game.seats() == FOUR_PLAYER_PLAY_AREA_POSITION.len() {
+        &FOUR_PLAYER_PLAY_AREA_POSITION
+    } else {
+        &PLAY_AREA_POSITION
+    };
+    for record in latest_records(game.trick(), game.seats()) {
+        let [x, y] = positions[room.relative_seat(record.seat, local.id)];
+        if record.pattern.is_none() {
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        "不出",
+                        TextStyle {
+                            font: assets.font.clone(),
+                            font_size: 36.,
+                            color: Color::ORANGE,
+                        },
+                    ),
+                    transform: Transform::from_xyz(x, y, 2.),
+                    ..Default::default()
+                },
+                PlayedCard,
+                RoomUIComponent,
+            ));
+            continue;
+        }
+        let mut cards = record.cards.clone();
+        sort_by_rank(&mut cards);
+        let mut substitutes = record.substitutes.clone();
+        let left = x - PLAYED_CARD_SPACING * (cards.len() as f32 - 1.) / 2.;
+        for (index, card) in cards.iter().enumerate() {
+            let x = left + PLAYED_CARD_SPACING * index as f32;
+            // 癞子染成金色 上方标出代替的点数
+            let substitute = substitutes
+                .iter()
+                .position(|(c, _)| c == card)
+                .map(|i| substitutes.remove(i).1);
+            let mut sprite = TextureAtlasSprite::new(get_sprite_index(card, &card_atlas));
+            if substitute.is_some() {
+                sprite.color = Color::rgb(1., 0.85, 0.4);
+            }
+            commands.spawn((
+                SpriteSheetBundle {
+                    texture_atlas: card_atlas.atlas.clone(),
+                    sprite,
+                    transform: Transform::from_xyz(x, y, 2. + index as f32 * 0.01)
+                        .with_scale(Vec3::splat(PLAYED_CARD_SCALE)),
+                    ..Default::default()
+                },
+                PlayedCard,
+                RoomUIComponent,
+            ));
+            if let Some(rank) = substitute {
+                commands.spawn((
+                    Text2dBundle {
+                        text: Text::from_section(
+                            rank.label(),
+                            TextStyle {
+                                font: assets.font.clone(),
+                                font_size: 24.,
+                                color: Color::GOLD,
+                            },
+                        ),
+                        transform: Transform::from_xyz(x, y + 56., 3.),
+                        ..Default::default()
+                    },
+                    PlayedCard,
+                    RoomUIComponent,
+                ));
+            }
+        }
+    }
+}
+
+// 其他座位明牌后 按Card.hide翻开显示手牌
+fn refresh_shown_hands(
+    mut commands: Commands,
+    card_atlas: Res<CardAtlas>,
+    room: Res<Room>,
+    local: Res<Player>,
+    query: Query<Entity, With<ShownHandCard>>,
+) {
+    if !room.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let Some(game) = &room.game else {
+        return;
+    };
+    let positions: &[[f32; 2]] = if game.seats() == FOUR_PLAYER_SHOWN_HAND_POSITION.len() {
+        &FOUR_PLAYER_SHOWN_HAND_POSITION
+    } else {
+        &SHOWN_HAND_POSITION
+    };
+    let viewer = room.seat_of(local.id);
+    for seat in 0..game.seats() {
+        let mut cards = game.visible_hand(seat, viewer);
+        if Some(seat) == viewer || cards.iter().any(|card| card.hide) {
+            continue;
+        }
+        sort_by_rank(&mut cards);
+        let [x, y] = positions[room.relative_seat(seat, local.id)];
+        let left = x - SHOWN_CARD_SPACING * (cards.len() as f32 - 1.) / 2.;
+        for (index, card) in cards.iter().enumerate() {
+            commands.spawn((
+                SpriteSheetBundle {
+                    texture_atlas: card_atlas.atlas.clone(),
+                    sprite: TextureAtlasSprite::new(get_sprite_index(card, &card_atlas)),
+                    transform: Transform::from_xyz(
+                        left + SHOWN_CARD_SPACING * index as f32,
+                        y,
+                        1. + index as f32 * 0.01,
+                    )
+                    .with_scale(Vec3::splat(SHOWN_CARD_SCALE)),
+                    ..Default::default()
+                },
+                ShownHandCard,
+                RoomUIComponent,
+            ));
+        }
+    }
+}
+
+// 出牌记录 最新的在上面
+fn refresh_history(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    room: Res<Room>,
+    list: Query<Entity, With<HistoryList>>,
+) {
+    if !room.is_changed() {
+        return;
+    }
+    let text_style = TextStyle {
+        font: assets.font.clone(),
+        font_size: 18.,
+        color: Color::WHITE,
+    };
+    let plays = room.game.as_ref().map(|game| game.plays.as_slice());
+    for entity in list.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for record in plays.unwrap_or_default().iter().rev() {
+                let name = room.players[record.seat]
+                    .as_ref()
+                    .map(|p| p.player.name())
+                    .unwrap_or_default();
+                let line = match &record.pattern {
+                    Some(pattern) => {
+                        let mut substitutes = record.substitutes.clone();
+                        let cards: Vec<String> = record
+                            .cards
+                            .iter()
+                            .map(
+                                |card| match substitutes.iter().position(|(c, _)| c == card) {
+                                    Some(i) => format!(
+                                        "{}({})",
+                                        card.label(),
+                                        substitutes.remove(i).1.label()
+                                    ),
+                                    None => card.label(),
+                                },
+                            )
+                            .collect();
+                        format!("{} {} {}", name, pattern.name(), cards.join(" "))
+                    }
+                    None => format!("{} 不出", name),
+                };
+                parent.spawn(TextBundle::from_section(line, text_style.clone()));
+            }
+        });
+    }
+}
+
+fn refresh_laizi(room: Res<Room>, mut query: Query<&mut Text, With<LaiziText>>) {
+    if !room.is_changed() {
+        return;
+    }
+    let laizi = room.game.as_ref().and_then(|game| game.laizi);
+    for mut text in query.iter_mut() {
+        text.sections[0].value = match laizi {
+            Some(rank) => format!("癞子: {}", rank.label()),
+            None => String::new(),
+        };
+    }
+}
+
+// 点击标题展开或收起出牌记录
+fn toggle_history(
+    query: Query<&Interaction, (Changed<Interaction>, With<HistoryToggle>)>,
+    mut list: Query<&mut Visibility, With<HistoryList>>,
+) {
+    if !query
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        return;
+    }
+    for mut visibility in list.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}